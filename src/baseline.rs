@@ -0,0 +1,53 @@
+use serde_json::Value;
+
+/// A metric that moved away from its baseline value by more than the tolerance
+pub struct Drift {
+    pub key: String,
+    pub baseline: f64,
+    pub current: f64,
+    /// Relative deviation, or absolute one when the baseline is 0
+    pub deviation: f64,
+}
+
+/// Last json object of a file holding one or more summaries, e.g. the
+/// combined summary of a '--per-file --json' run
+pub fn last_summary(contents: &str) -> Result<Value, String> {
+    let mut last = None;
+    for value in serde_json::Deserializer::from_str(contents).into_iter::<Value>() {
+        last = Some(value.map_err(|e| e.to_string())?);
+    }
+    match last {
+        Some(value @ Value::Object(_)) => Ok(value),
+        Some(_) => Err("the last json value is not a summary object".to_owned()),
+        None => Err("there is no json summary".to_owned()),
+    }
+}
+
+/// Numeric metrics present in both summaries that deviate by more than `tolerance`
+pub fn drifts(baseline: &Value, current: &Value, tolerance: f64) -> Vec<Drift> {
+    let (Some(baseline), Some(current)) = (baseline.as_object(), current.as_object()) else {
+        return vec![];
+    };
+
+    let mut drifts = vec![];
+    for (key, base) in baseline {
+        let (Some(base), Some(cur)) = (base.as_f64(), current.get(key).and_then(Value::as_f64))
+        else {
+            continue;
+        };
+        let deviation = if base == 0.0 {
+            cur.abs()
+        } else {
+            ((cur - base) / base).abs()
+        };
+        if deviation > tolerance {
+            drifts.push(Drift {
+                key: key.clone(),
+                baseline: base,
+                current: cur,
+                deviation,
+            });
+        }
+    }
+    drifts
+}
@@ -0,0 +1,50 @@
+use num_traits::{Float, FromPrimitive};
+
+use crate::exact;
+use crate::sample::SplitMix64;
+
+/// Settings of the bootstrap confidence intervals of quantiles
+pub struct Bootstrap {
+    pub resamples: usize,
+    pub confidence: f64,
+    pub seed: u64,
+}
+
+impl Bootstrap {
+    /// Percentile bootstrap interval of each quantile in `levels`: the data is
+    /// resampled with replacement `resamples` times and the interval spans the
+    /// central `confidence` fraction of the resampled quantiles. This costs
+    /// `resamples` sorts of the data. Intervals are `None` without any resample
+    pub fn quantile_intervals<T>(&self, sorted: &[T], levels: &[T]) -> Vec<Option<(T, T)>>
+    where
+        T: Float + FromPrimitive,
+    {
+        if sorted.is_empty() {
+            return vec![];
+        }
+
+        let mut rng = SplitMix64(self.seed);
+        let mut estimates = vec![Vec::with_capacity(self.resamples); levels.len()];
+        let mut resample = vec![T::zero(); sorted.len()];
+        for _ in 0..self.resamples {
+            for v in resample.iter_mut() {
+                *v = sorted[rng.below(sorted.len() as u64) as usize];
+            }
+            resample.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            for (q, estimates) in levels.iter().zip(estimates.iter_mut()) {
+                estimates.extend(exact::quantile(&resample, *q));
+            }
+        }
+
+        let tail = T::from_f64((1.0 - self.confidence) / 2.0).unwrap();
+        estimates
+            .into_iter()
+            .map(|mut estimates| {
+                estimates.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                let low = exact::quantile(&estimates, tail)?;
+                let high = exact::quantile(&estimates, T::one() - tail)?;
+                Some((low, high))
+            })
+            .collect()
+    }
+}
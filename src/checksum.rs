@@ -0,0 +1,43 @@
+use num_traits::Float;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Order-sensitive 64 bit FNV-1a hash of a stream of values. Values are
+/// hashed as little-endian f64 bits so the digest doesn't depend on the
+/// platform or toolchain.
+pub struct Checksum {
+    hash: u64,
+}
+
+impl Checksum {
+    pub fn new() -> Self {
+        Self { hash: FNV_OFFSET }
+    }
+
+    pub fn update<T: Float>(&mut self, val: T) {
+        self.hash_bytes(val.to_f64().unwrap().to_le_bytes());
+    }
+
+    /// Hash `val` seen `weight` times, repeated values being hashed along with
+    /// their count, then as little-endian u64 bits, so that large counts cost
+    /// no more than single values
+    pub fn update_weighted<T: Float>(&mut self, val: T, weight: u64) {
+        self.update(val);
+        if weight != 1 {
+            self.hash_bytes(weight.to_le_bytes());
+        }
+    }
+
+    fn hash_bytes(&mut self, bytes: [u8; 8]) {
+        for byte in bytes {
+            self.hash ^= u64::from(byte);
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    /// Digest as 16 hexadecimal digits
+    pub fn hex(&self) -> String {
+        format!("{:016x}", self.hash)
+    }
+}
@@ -0,0 +1,53 @@
+use std::io::{self, Write};
+
+use crossterm::{cursor, terminal, ExecutableCommand};
+
+/// Block of text redrawn in place, e.g. running values of the statistics
+pub struct RunningDisplay {
+    out: Box<dyn Write>,
+    // Lines written by the last draw, i.e. how far up to move to redraw
+    height: u16,
+    append: bool,
+}
+
+impl RunningDisplay {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self {
+            out,
+            height: 0,
+            append: false,
+        }
+    }
+
+    /// Write each block after the previous ones instead of in place
+    pub fn appending(out: Box<dyn Write>) -> Self {
+        Self {
+            append: true,
+            ..Self::new(out)
+        }
+    }
+
+    /// Replace the previously drawn block with `text` and a blank line.
+    /// The height is counted from the text itself so that blocks can grow
+    /// or shrink between draws
+    pub fn draw(&mut self, text: &str) -> io::Result<()> {
+        self.clear()?;
+        writeln!(self.out, "{text}")?;
+        self.out.flush()?;
+        if !self.append {
+            self.height = text.matches('\n').count() as u16 + 1;
+        }
+        Ok(())
+    }
+
+    /// Erase the previously drawn block
+    pub fn clear(&mut self) -> io::Result<()> {
+        if self.height > 0 {
+            self.out.execute(cursor::MoveUp(self.height))?;
+            self.out
+                .execute(terminal::Clear(terminal::ClearType::FromCursorDown))?;
+            self.height = 0;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::HashSet;
+
+use num_traits::Float;
+
+/// Bit pattern identifying a value, with 0 and -0 counted as the same value
+pub fn value_key<T: Float>(val: T) -> u64 {
+    let val = val.to_f64().unwrap();
+    if val == 0.0 {
+        0
+    } else {
+        val.to_bits()
+    }
+}
+
+/// Set of the values seen so far, holding at most `cap` of them
+pub struct Distinct {
+    seen: HashSet<u64>,
+    cap: usize,
+    /// Whether values were let through unchecked because the set was full
+    pub overflowed: bool,
+}
+
+impl Distinct {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            seen: HashSet::new(),
+            cap,
+            overflowed: false,
+        }
+    }
+
+    /// Whether `val` is seen for the first time. Once the set is full new
+    /// values are always reported as first seen
+    pub fn insert<T: Float>(&mut self, val: T) -> bool {
+        let bits = value_key(val);
+        if self.seen.contains(&bits) {
+            return false;
+        }
+        if self.seen.len() < self.cap {
+            self.seen.insert(bits);
+        } else {
+            self.overflowed = true;
+        }
+        true
+    }
+}
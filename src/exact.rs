@@ -0,0 +1,127 @@
+use std::mem::size_of;
+
+use num_traits::{Float, FromPrimitive};
+
+/// In-memory copy of every value, sorted at EOF to compute exact quantiles.
+pub struct ExactBuffer<T> {
+    values: Vec<T>,
+    capacity: usize,
+    sorted: bool,
+}
+
+impl<T> ExactBuffer<T>
+where
+    T: Float + FromPrimitive,
+{
+    /// Buffer at most `cap_bytes` worth of values
+    pub fn new(cap_bytes: usize) -> Self {
+        Self {
+            values: vec![],
+            capacity: cap_bytes / size_of::<T>(),
+            sorted: false,
+        }
+    }
+
+    /// Store `n` copies of a value, returns false if that would exceed the byte cap
+    pub fn push_repeated(&mut self, val: T, n: u64) -> bool {
+        if n > (self.capacity - self.values.len()) as u64 {
+            return false;
+        }
+        self.values.extend(std::iter::repeat_n(val, n as usize));
+        self.sorted = false;
+        true
+    }
+
+    pub fn sort(&mut self) {
+        self.values
+            .sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        self.sorted = true;
+    }
+
+    /// Sorted values, only available once `sort` has been called
+    pub fn sorted(&self) -> Option<&[T]> {
+        self.sorted.then_some(&self.values)
+    }
+
+    /// Sample variance computed with the two-pass algorithm, which avoids the
+    /// cancellation of one-pass formulas. Only available once `sort` has been called
+    pub fn variance(&self) -> Option<T> {
+        let values = self.sorted()?;
+        if values.len() < 2 {
+            return Some(T::zero());
+        }
+
+        // Accumulate in f64 so that f32 sums don't lose the mean
+        let n = values.len() as f64;
+        let mean = values.iter().map(|v| v.to_f64().unwrap()).sum::<f64>() / n;
+        let squares: f64 = values
+            .iter()
+            .map(|v| (v.to_f64().unwrap() - mean).powi(2))
+            .sum();
+
+        T::from_f64(squares / (n - 1.0))
+    }
+
+    /// Mean absolute deviation from the mean, only available once `sort` has been called
+    pub fn mad_mean(&self) -> Option<T> {
+        let values = self.sorted()?;
+        if values.is_empty() {
+            return None;
+        }
+
+        let n = values.len() as f64;
+        let mean = values.iter().map(|v| v.to_f64().unwrap()).sum::<f64>() / n;
+        let deviations: f64 = values
+            .iter()
+            .map(|v| (v.to_f64().unwrap() - mean).abs())
+            .sum();
+
+        T::from_f64(deviations / n)
+    }
+
+    /// Exact quantile, only available once `sort` has been called
+    pub fn quantile(&self, q: T) -> Option<T> {
+        quantile(self.sorted()?, q)
+    }
+}
+
+/// Quantile `q` of sorted values, linearly interpolating between the two
+/// closest ranks. Exact ranks and equal neighbours are returned as is, so
+/// that infinite values don't turn into NaN
+pub fn quantile<T: Float + FromPrimitive>(sorted: &[T], q: T) -> Option<T> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let rank = q * T::from_usize(sorted.len() - 1).unwrap();
+    let lower = rank.floor().to_usize().unwrap();
+    let upper = rank.ceil().to_usize().unwrap();
+    let frac = rank - rank.floor();
+
+    if lower == upper || frac == T::zero() || sorted[lower] == sorted[upper] {
+        return Some(sorted[lower]);
+    }
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * frac)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_buffer(values: &[f64]) -> ExactBuffer<f64> {
+        let mut buffer = ExactBuffer::new(usize::MAX);
+        values
+            .iter()
+            .for_each(|&v| assert!(buffer.push_repeated(v, 1)));
+        buffer.sort();
+        buffer
+    }
+
+    #[test]
+    fn two_pass_variance_avoids_cancellation() {
+        // The one-pass Σx² - n·mean² formula loses every digit on this offset
+        let offset = 1e9;
+        let buffer = sorted_buffer(&[offset + 4.0, offset + 7.0, offset + 13.0, offset + 16.0]);
+        assert!((buffer.variance().unwrap() - 30.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,103 @@
+use num_traits::Float;
+
+/// Running product and sum of squares, accumulated in double precision.
+///
+/// The product is kept as the sum of the logs of the magnitudes along
+/// with its sign, so that intermediate results never overflow or underflow
+/// however many values are multiplied. It is only exact up to the rounding
+/// of `ln` and `exp`, and is infinite or zero only if the final result is
+/// out of range, its log is reported alongside so that it stays usable.
+pub struct Extended {
+    log_abs: f64,
+    negative: bool,
+    zero: bool,
+    nan: bool,
+    sum_of_squares: f64,
+}
+
+impl Extended {
+    pub fn new() -> Self {
+        Self {
+            log_abs: 0.0,
+            negative: false,
+            zero: false,
+            nan: false,
+            sum_of_squares: 0.0,
+        }
+    }
+
+    /// Account for `val` seen `n` times
+    pub fn update<T: Float>(&mut self, val: T, n: u64) {
+        let (val, times) = (val.to_f64().unwrap(), n as f64);
+        self.sum_of_squares += val * val * times;
+        if val.is_nan() {
+            self.nan = true;
+        } else if val == 0.0 {
+            self.zero = true;
+        } else {
+            self.log_abs += val.abs().ln() * times;
+            self.negative ^= val < 0.0 && n % 2 == 1;
+        }
+    }
+
+    /// Natural log of the magnitude of the product, -inf if a value was 0
+    pub fn log_abs_product(&self) -> f64 {
+        match (self.nan, self.zero) {
+            (true, _) => f64::NAN,
+            (_, true) => f64::NEG_INFINITY,
+            _ => self.log_abs,
+        }
+    }
+
+    pub fn product(&self) -> f64 {
+        let sign = if self.negative { -1.0 } else { 1.0 };
+        sign * self.log_abs_product().exp()
+    }
+
+    pub fn sum_of_squares(&self) -> f64 {
+        self.sum_of_squares
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(values: &[(f64, u64)]) -> Extended {
+        let mut extended = Extended::new();
+        values.iter().for_each(|&(v, n)| extended.update(v, n));
+        extended
+    }
+
+    #[test]
+    fn product_overflowing_midway_is_recovered() {
+        // 1e200 * 1e200 overflows on its own, the final product doesn't
+        let extended = product(&[(1e200, 2), (-1e-300, 1)]);
+        assert!((extended.product() / -1e100 - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn out_of_range_product_keeps_its_log_and_sign() {
+        let extended = product(&[(-1e300, 3)]);
+        assert_eq!(extended.product(), f64::NEG_INFINITY);
+        assert!((extended.log_abs_product() - 900.0 * 10f64.ln()).abs() < 1e-9);
+
+        let extended = product(&[(1e-300, 2), (-1.0, 2)]);
+        assert_eq!(extended.product(), 0.0);
+        assert!((extended.log_abs_product() + 600.0 * 10f64.ln()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn zero_factor_gives_a_zero_product() {
+        let extended = product(&[(1e300, 5), (0.0, 1), (2.0, 1)]);
+        assert_eq!(extended.log_abs_product(), f64::NEG_INFINITY);
+        assert_eq!(extended.product(), 0.0);
+    }
+
+    #[test]
+    fn nan_factor_gives_a_nan_product() {
+        let extended = product(&[(0.0, 1), (f64::NAN, 1), (2.0, 1)]);
+        assert!(extended.log_abs_product().is_nan());
+        assert!(extended.product().is_nan());
+    }
+}
@@ -0,0 +1,78 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use num_traits::Float;
+
+/// Float wrapper ordered by `partial_cmp`, NaN values are never stored
+#[derive(PartialEq)]
+struct Ordered<T>(T);
+
+impl<T: Float> Eq for Ordered<T> {}
+
+impl<T: Float> PartialOrd for Ordered<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Float> Ord for Ordered<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.partial_cmp(&other.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// The `k` smallest and `k` largest values seen, kept in two bounded heaps
+pub struct Extremes<T> {
+    k: usize,
+    // Max-heap, the top is the largest of the smallest values
+    smallest: BinaryHeap<Ordered<T>>,
+    // Min-heap, the top is the smallest of the largest values
+    largest: BinaryHeap<Reverse<Ordered<T>>>,
+}
+
+impl<T: Float> Extremes<T> {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            // Grown as values come, `k` may be far larger than the input
+            smallest: BinaryHeap::new(),
+            largest: BinaryHeap::new(),
+        }
+    }
+
+    pub fn update(&mut self, val: T) {
+        if val.is_nan() {
+            return;
+        }
+
+        self.smallest.push(Ordered(val));
+        if self.smallest.len() > self.k {
+            self.smallest.pop();
+        }
+        self.largest.push(Reverse(Ordered(val)));
+        if self.largest.len() > self.k {
+            self.largest.pop();
+        }
+    }
+
+    /// Add `n` copies of a value, no more than `k` of which can be kept
+    pub fn update_repeated(&mut self, val: T, n: u64) {
+        for _ in 0..n.min(self.k as u64) {
+            self.update(val);
+        }
+    }
+
+    /// Smallest values in ascending order
+    pub fn smallest(&self) -> Vec<T> {
+        let mut values: Vec<T> = self.smallest.iter().map(|v| v.0).collect();
+        values.sort_by_key(|v| Ordered(*v));
+        values
+    }
+
+    /// Largest values in descending order
+    pub fn largest(&self) -> Vec<T> {
+        let mut values: Vec<T> = self.largest.iter().map(|v| v.0 .0).collect();
+        values.sort_by_key(|v| Reverse(Ordered(*v)));
+        values
+    }
+}
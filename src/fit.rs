@@ -0,0 +1,84 @@
+use std::f64::consts::SQRT_2;
+
+/// A distribution fitted by the method of moments
+pub struct Fit {
+    pub name: &'static str,
+    pub params: Vec<(&'static str, f64)>,
+    /// Kolmogorov-Smirnov statistic against the empirical CDF, lower is better
+    pub ks: f64,
+}
+
+/// Error function, Abramowitz and Stegun 7.1.26 (absolute error below 1.5e-7)
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let y = 1.0 - poly * (-x * x).exp();
+    y.copysign(x)
+}
+
+fn normal_cdf(x: f64, mu: f64, sigma: f64) -> f64 {
+    0.5 * (1.0 + erf((x - mu) / (sigma * SQRT_2)))
+}
+
+/// Largest distance between `cdf` and the empirical CDF of sorted values
+fn ks_statistic(sorted: &[f64], cdf: impl Fn(f64) -> f64) -> f64 {
+    let n = sorted.len() as f64;
+    sorted
+        .iter()
+        .enumerate()
+        .map(|(i, &x)| {
+            let f = cdf(x);
+            (f - i as f64 / n).max((i + 1) as f64 / n - f)
+        })
+        .fold(0.0, f64::max)
+}
+
+/// Fit a normal, log-normal and exponential distribution to sorted values,
+/// skipping the ones that can't describe the data, e.g. log-normal with
+/// non-positive values. Fits are sorted from best to worst.
+pub fn fit_all(sorted: &[f64]) -> Vec<Fit> {
+    let mut fits = vec![];
+    if sorted.len() < 2 || sorted.iter().any(|x| !x.is_finite()) {
+        return fits;
+    }
+
+    let n = sorted.len() as f64;
+    let mean = sorted.iter().sum::<f64>() / n;
+    let variance = sorted.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+    if variance <= 0.0 {
+        return fits;
+    }
+
+    let sigma = variance.sqrt();
+    fits.push(Fit {
+        name: "normal",
+        params: vec![("mu", mean), ("sigma", sigma)],
+        ks: ks_statistic(sorted, |x| normal_cdf(x, mean, sigma)),
+    });
+
+    if sorted[0] > 0.0 {
+        let log_variance = (1.0 + variance / (mean * mean)).ln();
+        let log_mean = mean.ln() - log_variance / 2.0;
+        let log_sigma = log_variance.sqrt();
+        fits.push(Fit {
+            name: "log-normal",
+            params: vec![("mu", log_mean), ("sigma", log_sigma)],
+            ks: ks_statistic(sorted, |x| normal_cdf(x.ln(), log_mean, log_sigma)),
+        });
+    }
+
+    if sorted[0] >= 0.0 {
+        let rate = 1.0 / mean;
+        fits.push(Fit {
+            name: "exponential",
+            params: vec![("lambda", rate)],
+            ks: ks_statistic(sorted, |x| 1.0 - (-rate * x).exp()),
+        });
+    }
+
+    fits.sort_by(|a, b| a.ks.total_cmp(&b.ks));
+    fits
+}
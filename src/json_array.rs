@@ -0,0 +1,168 @@
+use std::io::{self, BufRead};
+
+use serde_json::Value;
+
+use crate::reader::{Line, BOM};
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Streams the elements of a top-level JSON array one at a time, so that
+/// only the current element is ever held in memory. Numbers are yielded
+/// as text to go through the same parsing as line based input, with the
+/// byte offset of the element in the stream.
+pub struct JsonArrayReader {
+    reader: Box<dyn BufRead + Send>,
+    field: Option<String>,
+    offset: u64,
+    index: usize,
+    started: bool,
+    done: bool,
+}
+
+impl JsonArrayReader {
+    /// With `field`, elements are objects and the number is read from that key
+    pub fn new(reader: Box<dyn BufRead + Send>, field: Option<String>) -> Self {
+        Self {
+            reader,
+            field,
+            offset: 0,
+            index: 0,
+            started: false,
+            done: false,
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.reader.consume(amount);
+        self.offset += amount as u64;
+    }
+
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.reader.fill_buf()?.first().copied())
+    }
+
+    fn skip_whitespace(&mut self) -> io::Result<()> {
+        while let Some(b) = self.peek()? {
+            if !b.is_ascii_whitespace() {
+                break;
+            }
+            self.consume(1);
+        }
+        Ok(())
+    }
+
+    /// Raw bytes of the next element, `None` once the closing bracket is read
+    fn next_element(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if !self.started {
+            self.started = true;
+            if self.reader.fill_buf()?.starts_with(BOM) {
+                self.consume(BOM.len());
+            }
+            self.skip_whitespace()?;
+            if self.peek()? != Some(b'[') {
+                return Err(invalid("Input is not a JSON array".to_owned()));
+            }
+            self.consume(1);
+            self.skip_whitespace()?;
+            if self.peek()? == Some(b']') {
+                self.consume(1);
+                return Ok(None);
+            }
+        }
+
+        let mut element = vec![];
+        let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+        loop {
+            let Some(b) = self.peek()? else {
+                return Err(invalid("Unterminated JSON array".to_owned()));
+            };
+            self.consume(1);
+
+            if in_string {
+                in_string = escaped || b != b'"';
+                escaped = !escaped && b == b'\\';
+                element.push(b);
+                continue;
+            }
+            match b {
+                b',' if depth == 0 => break,
+                b']' if depth == 0 => {
+                    self.done = true;
+                    break;
+                }
+                b'[' | b'{' => depth += 1,
+                b']' | b'}' => depth = depth.saturating_sub(1),
+                b'"' => in_string = true,
+                _ => {}
+            }
+            element.push(b);
+        }
+
+        Ok(Some(element))
+    }
+
+    fn read_value(&mut self) -> io::Result<Option<(u64, Line)>> {
+        if self.done {
+            return Ok(None);
+        }
+        let start = self.offset;
+        let Some(element) = self.next_element()? else {
+            self.done = true;
+            return Ok(None);
+        };
+
+        let index = self.index;
+        self.index += 1;
+        let value: Value = serde_json::from_slice(&element)
+            .map_err(|e| invalid(format!("Invalid JSON in array element {index}: {e}")))?;
+
+        let value = match (&self.field, value) {
+            (None, value) => value,
+            (Some(field), Value::Object(mut object)) => object
+                .remove(field)
+                .ok_or_else(|| invalid(format!("Array element {index} has no field '{field}'")))?,
+            (Some(_), _) => {
+                return Err(invalid(format!("Array element {index} is not an object")));
+            }
+        };
+
+        let text = match value {
+            Value::String(s) => s,
+            value => value.to_string(),
+        };
+        Ok(Some((start, Line::Text(text))))
+    }
+}
+
+impl Iterator for JsonArrayReader {
+    type Item = io::Result<(u64, Line)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_value().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(input: &'static str, field: Option<&str>) -> Vec<f64> {
+        JsonArrayReader::new(Box::new(input.as_bytes()), field.map(str::to_owned))
+            .map(|element| match element.unwrap() {
+                (_, Line::Text(text)) => text.parse().unwrap(),
+                (_, Line::TooLong) => panic!("element too long"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bom_and_crlf_line_endings_are_skipped() {
+        assert_eq!(values("\u{feff}[1,\r\n2\r\n]\r\n", None), [1.0, 2.0]);
+        assert_eq!(
+            values("\u{feff}[\r\n{\"x\": 1},\r\n{\"x\": 2}\r\n]", Some("x")),
+            [1.0, 2.0]
+        );
+    }
+}
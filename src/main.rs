@@ -1,21 +1,85 @@
-use std::fmt::{Debug, Display};
+use std::fmt::{Debug, Display, LowerExp};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, IsTerminal, LineWriter};
+use std::mem::size_of;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::ops::{AddAssign, SubAssign};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
 use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{io, io::prelude::*};
 
-use anyhow::{bail, Result};
-use clap::Parser;
-use crossterm::{cursor, terminal, ExecutableCommand};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{ArgAction, Parser, ValueEnum};
 use num_traits::{Float, FromPrimitive};
 use serde::Serialize;
-use serde_json::{json, Value};
-use watermill::mean::Mean;
+use serde_json::json;
 use watermill::quantile::Quantile;
 use watermill::stats::Univariate;
-use watermill::variance::Variance;
 
 use thiserror::Error;
 
+mod baseline;
+mod bootstrap;
+mod checksum;
+mod display;
+mod distinct;
+mod exact;
+mod extended;
+mod extremes;
+mod fit;
+mod json_array;
+mod modes;
+mod parse;
+mod polling;
+mod query;
+mod reader;
+mod robust;
+mod sample;
+mod serve;
+mod sorted;
+mod sparkline;
+mod sum;
+mod summary;
+mod target;
+mod template;
+mod ticking;
+mod transform;
+mod units;
+mod value_counts;
+mod variance;
+mod window;
+
+use bootstrap::Bootstrap;
+use checksum::Checksum;
+use display::RunningDisplay;
+use distinct::Distinct;
+use exact::ExactBuffer;
+use extended::Extended;
+use extremes::Extremes;
+use json_array::JsonArrayReader;
+use polling::Redraw;
+use query::Query;
+use reader::{Line, LineReader};
+use robust::RobustScaler;
+use sample::Reservoir;
+use serve::Server;
+use sorted::SortedTail;
+use sparkline::Sparkline;
+use sum::NeumaierSum;
+use summary::{html_document, html_table, ColorChoice, Metric, Notation, Style, Summary};
+use target::TargetError;
+use template::Template;
+use ticking::Ticking;
+use transform::Expr;
+use units::Unit;
+use value_counts::ValueCounts;
+use variance::WeightedVariance;
+use window::TrimmedWindow;
+
 #[derive(Error, Debug)]
 pub enum FloatError {
     #[error("Could not parse number on line {lineno}: '{number}'")]
@@ -31,36 +95,566 @@ pub enum FloatError {
 /// exact but they should be good enough on large datasets to get
 /// an idea of the data you have.
 struct Cli {
+    /// Files to read numbers from, in order, instead of stdin
+    files: Vec<PathBuf>,
+    /// Also print a separate summary for each input file before the combined one
+    #[arg(long)]
+    per_file: bool,
+    /// Also print a separate summary for each segment of the input, segments being
+    /// separated by lines equal to MARKER, e.g. '---' or '' for blank lines. Each one
+    /// is written when its marker is read, except in html tables
+    #[arg(long, value_name = "MARKER", allow_hyphen_values = true)]
+    segment_on: Option<String>,
     /// Use f64 instead of f32, increasing precision but also memory usage
     #[arg(short, long)]
     use_doubles: bool,
-    /// Print results as parsable json
+    /// Output format of the results
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    /// Print results as parsable json, same as '--format json'
     #[arg(short, long)]
     json: bool,
+    /// Print results as indented json, easier to read by eye, same as '--format json-pretty'
+    #[arg(long)]
+    json_pretty: bool,
+    /// With '--format html' print a full html document instead of a table fragment
+    #[arg(long)]
+    html_standalone: bool,
+    /// Stream a json summary to stdout every POLLING lines, one object per line.
+    /// The final summary is the last record
+    #[arg(long)]
+    ndjson_stream: bool,
+    /// Flush stdout after every line, e.g. each --ndjson-stream record, for live
+    /// consumers. Output is block buffered otherwise
+    #[arg(long)]
+    line_buffered: bool,
+    /// Also write each metric of the final summary to its own file in DIR, named after
+    /// its json key (e.g. 'mean') and holding the value and a newline. Existing files are overwritten
+    #[arg(long, value_name = "DIR")]
+    split_dir: Option<PathBuf>,
+    /// Write counts as json strings, so that they are read at full precision by
+    /// consumers limited to 53 bit integers (e.g. JavaScript). This affects every
+    /// top level integer: count, raw_count, lines, modes, mode_bins, distinct_values,
+    /// quantile_sample_size, nan_coerced and the *_dropped, *_skipped and
+    /// out_of_range_clipped counts. Floats such as min and max stay numbers
+    #[arg(long)]
+    bignum_strings: bool,
+    /// Always write every json key, null when not computed, so the output has the
+    /// same shape whatever the options. Keys of --tail-percentiles and --query depend on
+    /// their values, so they are only added when set
+    #[arg(long)]
+    stable_schema: bool,
+    /// Write each text summary as TEMPLATE with '{key}' placeholders replaced by
+    /// the values of the metrics of that json key, e.g. 'mean={mean} (n={count})'.
+    /// Use '{{' and '}}' for literal braces
+    #[arg(long, conflicts_with_all = ["json", "json_pretty", "ndjson_stream"])]
+    template: Option<Template>,
+    /// Tag the output with a name, useful when collecting results of several runs
+    #[arg(short, long)]
+    label: Option<String>,
+    /// How floating point values are written in text output
+    #[arg(long, value_enum, default_value_t = Notation::Decimal)]
+    notation: Notation,
+    /// Write undefined metrics as STRING in text output, e.g. 'null' or '', json
+    /// output keeps null
+    #[arg(long, value_name = "STRING", default_value = "NA")]
+    na_token: String,
+    /// Also summarize negative and non-negative values separately, NaN values
+    /// are only counted in the combined summary
+    #[arg(long)]
+    split_sign: bool,
+    /// Report a hash of the values used, after filtering and in input order,
+    /// to check that two runs processed the same data
+    #[arg(long)]
+    checksum: bool,
+    /// Don't print warnings, e.g. about precision loss or a full --exact buffer
+    #[arg(long)]
+    no_warnings: bool,
+    /// When to color text output and running values
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// If computing statistics panics on a value, print the statistics so far to
+    /// stderr and exit with code 70. This is best effort: only panics while
+    /// updating the estimators are caught, and their state may be off by that value
+    #[arg(long)]
+    recover_from_panic: bool,
+    /// Compare the final summary to one saved with --json, failing if a metric
+    /// deviates from it by more than --tolerance
+    #[arg(long, value_name = "PATH")]
+    baseline: Option<PathBuf>,
+    /// Largest allowed relative deviation from --baseline, absolute when the baseline value is 0
+    #[arg(long, value_name = "F", default_value_t = 0.01, requires = "baseline")]
+    tolerance: f64,
+    /// Print the resolved configuration as json to stderr before processing
+    #[arg(long)]
+    show_config: bool,
+    /// Print the JSON Schema of a json summary record given the other flags, then exit
+    #[arg(long)]
+    schema: bool,
     /// Hide running values for metrics.
     #[arg(short = 'n', long)]
     hide_running: bool,
-    /// Set polling interval for showing running values of statistics
+    /// Write each running snapshot as a new block under a '--- line N ---' header
+    /// instead of redrawing in place, keeping a log of how the values evolved
+    #[arg(long, conflicts_with = "hide_running")]
+    running_append: bool,
+    /// Stop reading after this wall-clock time, e.g. '30s', and report on the values
+    /// read so far. Summaries are then marked as partial
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    abort_after: Option<Duration>,
+    /// With --hide-running, print the number of lines processed to stderr at this
+    /// interval, e.g. '30s', so that long silent runs are not taken for hung
+    #[arg(long, value_name = "DURATION", requires = "hide_running", value_parser = parse_duration)]
+    heartbeat: Option<Duration>,
+    /// Draw running values to 'stderr', 'stdout' or a terminal path such as /dev/pts/1,
+    /// to keep them apart from the results when streams are merged
+    #[arg(long, value_name = "TARGET", default_value = "stderr")]
+    display_to: DisplayTarget,
+    /// Fit running values in this many columns so that they never wrap, switching
+    /// to shorter notations when needed. Defaults to the terminal width, 0 disables
+    #[arg(long, value_name = "COLUMNS")]
+    display_width: Option<u16>,
+    /// Draw a sparkline of the recent running values of the metric of that json
+    /// key, e.g. 'mean', under the running display when it is a terminal
+    #[arg(long, value_name = "METRIC")]
+    sparkline: Option<String>,
+    /// Set polling interval for showing running values of statistics, redraws
+    /// happen at most every 50ms however small it is
     #[arg(short, long, default_value_t = 1000)]
-    polling: usize,
-    /// Skip first line, e.g. header of a csv file
+    polling: u64,
+    /// Every POLLING lines, replace the contents of PATH with a json object holding the
+    /// number of lines read and the elapsed seconds, for external progress monitoring.
+    /// The file is written once more at EOF with '"done": true'
+    #[arg(long, value_name = "PATH")]
+    progress_file: Option<PathBuf>,
+    /// Serve the running json summary over HTTP at ADDR, e.g. '127.0.0.1:8080',
+    /// answering 'GET /stats'. It is updated every POLLING lines until the input ends
+    #[arg(long, value_name = "ADDR")]
+    serve: Option<SocketAddr>,
+    /// Report on stderr how long was spent drawing running values against reading
+    /// and computing statistics
+    #[arg(long)]
+    timing: bool,
+    /// Report on stderr as json how many lines were read, parsed and skipped, their
+    /// average length and the throughput of reading against that of parsing alone
+    #[arg(long)]
+    parse_stats: bool,
+    /// Dump the state of the streaming quantile estimators on stderr as json at EOF:
+    /// the heights of the five P² markers with their actual and desired positions
+    #[arg(long, conflicts_with_all = ["approx_median", "range_only"])]
+    debug_quantiles: bool,
+    /// Redraw running values about every 200ms whatever the rate of the input,
+    /// instead of every POLLING lines
+    #[arg(long, conflicts_with = "polling")]
+    poll_adaptive: bool,
+    /// Skip first line, e.g. header of a csv file. Implies --header. With several
+    /// files only the first one has a header, the others are read as its continuation
     #[arg(short, long)]
     skip_header: bool,
+    /// Read the first line of the first input as a column name used to label the output,
+    /// it is not counted as data
+    #[arg(long)]
+    header: bool,
+    /// Read the input as a single json array of numbers, streamed element by element
+    #[arg(long, conflicts_with_all = ["header", "skip_header"])]
+    json_array: bool,
+    /// With --json-array, read numbers from this field of array elements that are objects
+    #[arg(long, value_name = "FIELD", requires = "json_array")]
+    json_field: Option<String>,
+    /// Maximum length of a line in bytes, longer lines are an error unless
+    /// --skip-long-lines is set. Unlimited by default
+    #[arg(long, value_name = "BYTES")]
+    max_line_length: Option<usize>,
+    /// Capacity in bytes of the input read buffers, between 1 KiB and 1 GiB
+    #[arg(long, value_name = "BYTES", default_value_t = 1 << 16, value_parser = parse_buffer_size)]
+    buffer_size: usize,
+    /// Skip and count lines longer than --max-line-length instead of failing
+    #[arg(long, requires = "max_line_length")]
+    skip_long_lines: bool,
+    /// Also give the byte offset in the input of lines in error messages, lines
+    /// too long always have theirs
+    #[arg(long)]
+    byte_offsets: bool,
+    /// Skip and count lines that are not numbers, or are NaN, instead of failing
+    #[arg(long)]
+    tolerant: bool,
+    /// With --tolerant, exit with an error after printing results if more than this
+    /// fraction of lines were skipped as bad values
+    #[arg(long, value_name = "F", requires = "tolerant", value_parser = parse_fraction)]
+    max_bad_fraction: Option<f64>,
+    /// Count NaN values as 0, e.g. when missing means no activity. The number of
+    /// coerced values is reported
+    #[arg(long)]
+    nan_as_zero: bool,
+    /// Also count lines equal to TOKEN, e.g. 'NA' or 'null', as 0 with --nan-as-zero
+    #[arg(long, value_name = "TOKEN", requires = "nan_as_zero")]
+    missing_token: Option<String>,
+    /// Require the whole line to be a number. With '--strict false' only the
+    /// leading number is read and trailing text such as units is ignored, e.g. '3.14ms'
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = ArgAction::Set)]
+    strict: bool,
+    /// Read pre-aggregated 'value count' lines, each value counting as many times
+    /// as its integer count without being expanded. Streaming quantiles see each
+    /// value at most 65536 times, use --exact for exact quantiles of large counts.
+    /// --checksum hashes values along with their count
+    #[arg(long, conflicts_with_all = ["distinct", "robust_standardize", "sorted"])]
+    weighted_counts: bool,
+    /// Read values with a unit suffix, e.g. '3.5ms', '2GiB' or '4k', and convert
+    /// them to --base-unit. Time (ns to h), byte (B to TiB) and bare multiplier
+    /// (k to T, Ki to Ti) units are known, unknown ones are bad values
+    #[arg(long)]
+    unit_parse: bool,
+    /// Unit values are converted to with --unit-parse, by default seconds, bytes
+    /// or no unit depending on the unit of each value
+    #[arg(long, value_name = "UNIT", requires = "unit_parse")]
+    base_unit: Option<Unit>,
+    /// Apply an arithmetic transform to each value before computing statistics,
+    /// e.g. 'x*1000' or '(x-32)*5/9'. Supports + - * / ^ and parentheses
+    #[arg(short, long, value_name = "EXPR")]
+    transform: Option<Expr>,
+    /// Compute statistics over absolute values, so that the min is the smallest magnitude
+    #[arg(long)]
+    abs: bool,
+    /// Add C to every value, after --transform and --abs, e.g. to move zeros and
+    /// negative values into positive territory. The shift is reported so that
+    /// results can be shifted back
+    #[arg(long, value_name = "C", allow_negative_numbers = true)]
+    shift: Option<f64>,
+    /// Ignore the first N parsed values, e.g. unstable readings while a sensor warms
+    /// up. The number of values dropped is reported
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    drop_first: u64,
+    /// Drop infinite values so that every metric is computed over finite data only
+    #[arg(short, long)]
+    finite_only: bool,
+    /// Report additional tail percentiles given in percent form, e.g. '99,99.9,99.99'
+    #[arg(long, value_name = "PERCENTS", value_delimiter = ',', value_parser = parse_percent)]
+    tail_percentiles: Vec<f64>,
+    /// Write tail percentiles with DIGITS decimals in text output, right aligned
+    /// so that they line up
+    #[arg(long, value_name = "DIGITS", requires = "tail_percentiles")]
+    percentile_digits: Option<usize>,
+    /// Write values whose running z-score exceeds --zscore-threshold to this file,
+    /// as 'line_number<TAB>zscore<TAB>value' records, line numbers starting at 1 and
+    /// counting header lines
+    #[arg(long, value_name = "PATH")]
+    outliers_out: Option<PathBuf>,
+    /// Absolute z-score above which a value is considered an outlier
+    #[arg(long, default_value_t = 3.0)]
+    zscore_threshold: f64,
+    /// Write each value standardized as (x - median) / MAD to stdout, one per line,
+    /// and the summary to stderr. Exact medians are used with --exact, otherwise each
+    /// value is scaled on arrival against running estimates of the values before it,
+    /// which are rough for the first values. The MAD is not rescaled to match a
+    /// standard deviation, values with an unknown or zero MAD are written as the --na-token
+    #[arg(long, conflicts_with_all = ["ndjson_stream", "cdf_points"])]
+    robust_standardize: bool,
+    /// Skip the quantile estimators and report the mean as an approximate median
+    /// under `approx_median`, `median` and the quartiles being NA. Only sensible for
+    /// symmetric data
+    #[arg(long, conflicts_with_all = ["tail_percentiles", "exact"])]
+    approx_median: bool,
+    /// Report a moving trimmed mean over the last WINDOW values
+    #[arg(long)]
+    window: Option<NonZeroUsize>,
+    /// Fraction of values dropped from each end of the window for the trimmed mean
+    #[arg(long, default_value_t = 0.1, requires = "window", value_parser = parse_trim)]
+    trim: f64,
+    /// Keep every value in memory to compute exact quantiles and a two-pass variance
+    #[arg(short, long)]
+    exact: bool,
+    /// Maximum memory in bytes used to buffer values with --exact, past which it
+    /// falls back to streaming estimates with a warning. Defaults to 1 GiB
+    #[arg(long, value_name = "BYTES", default_value_t = 1 << 30, requires = "exact")]
+    exact_cap: usize,
+    /// Buffer up to N values for exact results, past which quantiles silently
+    /// come from the streaming estimators. These are fed every value from the
+    /// start so the transition needs no warm-up, whether exact quantiles were
+    /// used is reported
+    #[arg(long, value_name = "N", conflicts_with_all = ["exact", "approx_median", "range_only"])]
+    exact_threshold: Option<NonZeroUsize>,
+    /// Input values are in ascending order: compute exact quantiles without sorting,
+    /// dropping values ranked below the lowest reported quantile as they come.
+    /// Memory is still O(n), about three quarters of the values being kept with the
+    /// default quartiles, so this saves the sort rather than bounding memory.
+    /// Unsorted values are an error
+    #[arg(long, conflicts_with_all = ["exact", "exact_threshold", "approx_median", "range_only"])]
+    sorted: bool,
+    /// Compare the streaming quantile estimates against the exact ones, reporting their errors
+    #[arg(long, requires = "exact")]
+    percentile_error: bool,
+    /// Also report the product and the sum of squares of the values. The product is
+    /// accumulated as a sum of logs so it can't overflow midway, its log is reported too
+    #[arg(long)]
+    extended: bool,
+    /// Only compute statistics over distinct values, dropping repeats of a value
+    /// already seen. The raw count and number of duplicates are reported
+    #[arg(long)]
+    distinct: bool,
+    /// Maximum number of distinct values remembered by --distinct and
+    /// --value-counts, past it new values are no longer deduplicated or counted
+    /// and a warning is printed
+    #[arg(long, value_name = "N", default_value_t = 1 << 24)]
+    max_distinct: usize,
+    /// Count the occurrences of each distinct value and report the N most
+    /// frequent with their counts and proportions, for discrete data
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "10")]
+    value_counts: Option<NonZeroUsize>,
+    /// Treat values as predictions of TARGET and also report the mean absolute
+    /// error, root mean squared error and mean bias (value - TARGET)
+    #[arg(long, value_name = "TARGET", allow_negative_numbers = true)]
+    target: Option<f64>,
+    /// Instead of the summary, write the values at M equally spaced percentiles
+    /// (100/M, 200/M, ..., 100) as 'percentile,value' csv rows, a downsampled empirical CDF
+    #[arg(long, value_name = "M", requires = "exact")]
+    cdf_points: Option<NonZeroUsize>,
+    /// Compute quantiles from a uniform random sample of N values, sorted at EOF:
+    /// memory is bounded like the streaming estimates but multimodal data is handled better
+    #[arg(long, value_name = "N", conflicts_with_all = ["exact", "approx_median", "range_only"])]
+    sample_quantiles: Option<NonZeroUsize>,
+    /// Seed of the random sampling of --sample-quantiles and --quantile-ci, for
+    /// reproducible results
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Estimate the number of modes of the exact values and their locations from
+    /// the local maxima of a smoothed histogram, to flag multimodal data
+    #[arg(long, requires = "exact")]
+    detect_modes: bool,
+    /// Number of histogram bins of --detect-modes, by default from the
+    /// Freedman-Diaconis rule within 10 and 200
+    #[arg(long, value_name = "BINS", requires = "detect_modes")]
+    mode_bins: Option<NonZeroUsize>,
+    /// Smallest prominence of a --detect-modes peak, as a fraction of the tallest bin
+    #[arg(long, value_name = "F", default_value_t = 0.1, requires = "detect_modes", value_parser = parse_fraction)]
+    mode_prominence: f64,
+    /// Report bootstrap confidence intervals of the exact quantiles. This sorts
+    /// the data once per resample, so it is slow on large inputs
+    #[arg(long, requires = "exact")]
+    quantile_ci: bool,
+    /// Number of resamples of --quantile-ci
+    #[arg(
+        long,
+        value_name = "B",
+        default_value = "1000",
+        requires = "quantile_ci"
+    )]
+    bootstrap: NonZeroUsize,
+    /// Confidence level of --quantile-ci intervals
+    #[arg(long, default_value_t = 0.95, requires = "quantile_ci", value_parser = parse_fraction)]
+    confidence: f64,
+    /// Fit a normal, log-normal and exponential distribution with the method of
+    /// moments, reporting their parameters, Kolmogorov-Smirnov statistic and the best fit
+    #[arg(long, requires = "exact")]
+    fit: bool,
+    /// Answer a query against the exact values, 'q P' for the quantile at level
+    /// P in [0, 1] or 'rank X' for the fraction of values lower than or equal to X.
+    /// Can be repeated
+    #[arg(long, value_name = "QUERY", requires = "exact")]
+    query: Vec<Query>,
+    /// Report the exact empirical CDF at each X, the fraction of values lower than
+    /// or equal to it, found by binary search over the sorted values
+    #[arg(
+        long,
+        value_name = "X",
+        value_delimiter = ',',
+        allow_negative_numbers = true,
+        requires = "exact"
+    )]
+    cdf: Vec<f64>,
+    /// Only compute the min, max and range of finite values, skipping every other estimator
+    #[arg(long, conflicts_with_all = ["exact", "approx_median", "tail_percentiles", "window"])]
+    range_only: bool,
+    /// Drop values outside of the inclusive range LO,HI, e.g. '0,100' for percentages
+    #[arg(long, visible_alias = "reject-range", value_name = "LO,HI")]
+    clamp_range: Option<Bounds>,
+    /// Clamp out of range values to the --clamp-range bounds instead of dropping them
+    #[arg(long, requires = "clamp_range")]
+    clip: bool,
+    /// Report the K smallest and K largest values, K=1 is the min and max
+    #[arg(long, value_name = "K")]
+    k_extremes: Option<NonZeroUsize>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// Tab separated 'label: value' lines
+    Text,
+    /// Compact json object
+    Json,
+    /// Indented json object
+    JsonPretty,
+    /// Html table, with a column per summary
+    Html,
+}
+
+/// Where the running display is drawn: 'stderr', 'stdout' or the path of a terminal
+#[derive(Clone)]
+enum DisplayTarget {
+    Stderr,
+    Stdout,
+    Path(PathBuf),
+}
+
+impl FromStr for DisplayTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "stderr" => Self::Stderr,
+            "stdout" => Self::Stdout,
+            path => Self::Path(PathBuf::from(path)),
+        })
+    }
+}
+
+impl DisplayTarget {
+    fn open(&self) -> Result<Box<dyn Write>> {
+        Ok(match self {
+            Self::Stderr => Box::new(io::stderr()),
+            Self::Stdout => Box::new(io::stdout()),
+            Self::Path(path) => Box::new(
+                OpenOptions::new()
+                    .write(true)
+                    .open(path)
+                    .with_context(|| format!("Could not open '{}'", path.display()))?,
+            ),
+        })
+    }
+}
+
+/// Inclusive bounds given as 'LO,HI'
+#[derive(Clone, Copy)]
+struct Bounds {
+    lo: f64,
+    hi: f64,
+}
+
+impl FromStr for Bounds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (lo, hi) = s
+            .split_once(',')
+            .ok_or_else(|| format!("'{s}' is not of the form LO,HI"))?;
+        let lo: f64 = lo
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{lo}' is not a number"))?;
+        let hi: f64 = hi
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{hi}' is not a number"))?;
+        if lo > hi {
+            return Err(format!("lower bound {lo} is greater than upper bound {hi}"));
+        }
+        Ok(Self { lo, hi })
+    }
+}
+
+fn parse_trim(s: &str) -> Result<f64, String> {
+    let t: f64 = s.parse().map_err(|_| format!("'{s}' is not a number"))?;
+    if !(0.0..0.5).contains(&t) {
+        return Err(format!("{t} is not in [0, 0.5)"));
+    }
+    Ok(t)
+}
+
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    let f: f64 = s.parse().map_err(|_| format!("'{s}' is not a number"))?;
+    if !(0.0..=1.0).contains(&f) {
+        return Err(format!("{f} is not between 0 and 1"));
+    }
+    Ok(f)
+}
+
+fn parse_buffer_size(s: &str) -> Result<usize, String> {
+    let size: usize = s
+        .parse()
+        .map_err(|_| format!("'{s}' is not a size in bytes"))?;
+    if !((1 << 10)..=(1 << 30)).contains(&size) {
+        return Err(format!("{size} is not between 1024 and 1073741824 bytes"));
+    }
+    Ok(size)
+}
+
+fn parse_percent(s: &str) -> Result<f64, String> {
+    let p: f64 = s.parse().map_err(|_| format!("'{s}' is not a number"))?;
+    if p <= 0.0 || p >= 100.0 {
+        return Err(format!("{p} is not strictly between 0 and 100"));
+    }
+    Ok(p)
+}
+
+/// Duration with a unit among ms, s, m and h, e.g. '500ms' or '30s'
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("'{s}' is not a duration"))?;
+    let seconds = match unit {
+        "ms" => value / 1000.0,
+        "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return Err(format!("'{s}' needs a unit among ms, s, m and h")),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+/// Variance to mean ratio, about 1 for Poisson distributed counts and
+/// NA when the mean is zero
+fn dispersion_index<T: Float>(variance: T, mean: T) -> Metric<T> {
+    if mean == T::zero() {
+        Metric::NA
+    } else {
+        Metric::Float(variance / mean)
+    }
 }
 
 struct Stats<T>
 where
     T: Float + FromPrimitive + AddAssign + SubAssign,
 {
-    mean: Mean<T>,
     median: Quantile<T>,
     q1: Quantile<T>,
     q3: Quantile<T>,
-    variance: Variance<T>,
-    count: usize,
+    percentiles: Vec<(String, T, Quantile<T>)>,
+    variance: WeightedVariance,
+    // Sum of |x - mean| against the mean of the previous values, an approximation since
+    // early values are compared to an unsettled mean
+    mad_mean: NeumaierSum,
+    count: u64,
+    sum: NeumaierSum,
     min: T,
     max: T,
     initialized: bool,
+    approx_median: bool,
+    window: Option<TrimmedWindow<T>>,
+    exact: Option<ExactBuffer<T>>,
+    exact_overflowed: bool,
+    // Whether falling back to streaming is expected and reported
+    exact_threshold: bool,
+    sorted: Option<SortedTail<T>>,
+    // Whether a value smaller than the previous one was seen
+    unsorted: bool,
+    range_only: bool,
+    extremes: Option<Extremes<T>>,
+    // Counts and number of most frequent values reported
+    value_counts: Option<(ValueCounts, usize)>,
+    percentile_error: bool,
+    checksum: Option<Checksum>,
+    fit: bool,
+    queries: Vec<Query>,
+    cdf: Vec<f64>,
+    target: Option<TargetError<T>>,
+    extended: Option<Extended>,
+    sample: Option<Reservoir<T>>,
+    bootstrap: Option<Bootstrap>,
+    // Histogram bins, automatic if None, and prominence threshold
+    modes: Option<(Option<usize>, f64)>,
 }
 
 impl<T> Stats<T>
@@ -69,130 +663,1997 @@ where
 {
     pub fn default() -> Self {
         Self {
-            mean: Mean::new(),
             median: Quantile::new(T::from_f32(0.5).unwrap()).unwrap(),
             q1: Quantile::new(T::from_f32(0.25).unwrap()).unwrap(),
             q3: Quantile::new(T::from_f32(0.75).unwrap()).unwrap(),
-            variance: Variance::default(),
+            percentiles: vec![],
+            variance: WeightedVariance::default(),
+            mad_mean: NeumaierSum::default(),
             count: 0,
+            sum: NeumaierSum::default(),
             min: Float::infinity(),
             max: Float::neg_infinity(),
             initialized: false,
+            approx_median: false,
+            window: None,
+            exact: None,
+            exact_overflowed: false,
+            exact_threshold: false,
+            sorted: None,
+            unsorted: false,
+            range_only: false,
+            extremes: None,
+            value_counts: None,
+            percentile_error: false,
+            checksum: None,
+            fit: false,
+            queries: vec![],
+            cdf: vec![],
+            target: None,
+            extended: None,
+            sample: None,
+            bootstrap: None,
+            modes: None,
         }
     }
 
+    /// Report the error of the streaming quantiles against the exact ones
+    pub fn with_percentile_error(mut self) -> Self {
+        self.percentile_error = true;
+        self
+    }
+
+    /// Label, key, level and streaming estimator of every reported quantile
+    fn quantiles(&self) -> Vec<(&str, &str, T, &Quantile<T>)> {
+        let mut quantiles = vec![
+            ("Median", "median", T::from_f32(0.5).unwrap(), &self.median),
+            ("q1", "q1", T::from_f32(0.25).unwrap(), &self.q1),
+            ("q3", "q3", T::from_f32(0.75).unwrap(), &self.q3),
+        ];
+        for (label, q, streaming) in self.percentiles.iter() {
+            quantiles.push((label, label, *q, streaming));
+        }
+        quantiles
+    }
+
+    /// Marker heights and positions of the P² estimator of every reported quantile
+    pub fn quantile_markers(&self) -> serde_json::Value {
+        let markers: serde_json::Map<String, serde_json::Value> = self
+            .quantiles()
+            .into_iter()
+            .map(|(_, key, q, streaming)| {
+                let state = serde_json::to_value(streaming).unwrap_or_default();
+                let markers = json!({
+                    "q": q,
+                    "heights": state["heights"],
+                    "positions": state["position"],
+                    "desired_positions": state["marker_position"],
+                });
+                (key.to_owned(), markers)
+            })
+            .collect();
+        markers.into()
+    }
+
+    /// Also track the product and sum of squares of the values
+    pub fn with_extended(mut self) -> Self {
+        self.extended = Some(Extended::new());
+        self
+    }
+
+    /// Report error metrics of the values against a constant `target`
+    pub fn with_target(mut self, target: T) -> Self {
+        self.target = Some(TargetError::new(target));
+        self
+    }
+
+    /// Fit common distributions to the exact values once finalized
+    pub fn with_fit(mut self) -> Self {
+        self.fit = true;
+        self
+    }
+
+    /// Hash the values, in order, to check that two runs used the same data
+    pub fn with_checksum(mut self) -> Self {
+        self.checksum = Some(Checksum::new());
+        self
+    }
+
+    /// Keep the `k` smallest and largest values
+    pub fn with_extremes(mut self, k: usize) -> Self {
+        self.extremes = Some(Extremes::new(k));
+        self
+    }
+
+    /// Answer `queries` against the exact values once finalized
+    pub fn with_queries(mut self, queries: Vec<Query>) -> Self {
+        self.queries = queries;
+        self
+    }
+
+    /// Report the empirical CDF at every value of `xs` once finalized
+    pub fn with_cdf(mut self, xs: Vec<f64>) -> Self {
+        self.cdf = xs;
+        self
+    }
+
+    /// Count each distinct value, up to `cap` of them, and report the `n` most frequent
+    pub fn with_value_counts(mut self, n: usize, cap: usize) -> Self {
+        self.value_counts = Some((ValueCounts::new(cap), n));
+        self
+    }
+
+    /// Only track the count, min and max of finite values
+    pub fn with_range_only(mut self) -> Self {
+        self.range_only = true;
+        self
+    }
+
+    /// Buffer up to `cap_bytes` of values to compute exact quantiles once
+    /// `finalize` is called. The streaming estimators are still updated so
+    /// they can take over if the cap is reached.
+    pub fn with_exact(mut self, cap_bytes: usize) -> Self {
+        self.exact = Some(ExactBuffer::new(cap_bytes));
+        self
+    }
+
+    /// Buffer up to `n` values, falling back to streaming estimates past it
+    pub fn with_exact_threshold(mut self, n: usize) -> Self {
+        self.exact = Some(ExactBuffer::new(n.saturating_mul(size_of::<T>())));
+        self.exact_threshold = true;
+        self
+    }
+
+    /// Exact quantiles of values known to arrive sorted, to be called once the
+    /// tail percentiles are added
+    pub fn with_sorted(mut self) -> Self {
+        let lowest = self
+            .percentiles
+            .iter()
+            .map(|(_, q, _)| q.to_f64().unwrap())
+            .fold(0.25, f64::min);
+        self.sorted = Some(SortedTail::new(lowest));
+        self
+    }
+
+    /// Prepare exact results, to be called once all values are seen
+    pub fn finalize(&mut self) {
+        if let Some(exact) = self.exact.as_mut() {
+            exact.sort();
+        }
+        if let Some(sample) = self.sample.as_mut() {
+            sample.sort();
+        }
+    }
+
+    /// Compute quantiles from a uniform sample of `size` values once finalized
+    pub fn with_sample_quantiles(mut self, size: usize, seed: u64) -> Self {
+        self.sample = Some(Reservoir::new(size, seed));
+        self
+    }
+
+    /// Detect the modes of the exact values once finalized, see `modes::detect_modes`
+    pub fn with_modes(mut self, bins: Option<usize>, prominence: f64) -> Self {
+        self.modes = Some((bins, prominence));
+        self
+    }
+
+    /// Report bootstrap confidence intervals of the exact quantiles
+    pub fn with_quantile_intervals(mut self, bootstrap: Bootstrap) -> Self {
+        self.bootstrap = Some(bootstrap);
+        self
+    }
+
+    /// Quantile of the exact values or of the sample, once finalized
+    fn sorted_quantile(&self, q: T) -> Option<T> {
+        if let Some(sorted) = self.sorted.as_ref() {
+            return sorted.quantile(q);
+        }
+        let exact = self.exact.as_ref().and_then(|e| e.quantile(q));
+        exact.or_else(|| self.sample.as_ref().and_then(|s| s.quantile(q)))
+    }
+
+    /// Keep the last `size` values to report a moving trimmed mean
+    pub fn with_trimmed_window(mut self, size: usize, trim: f64) -> Self {
+        self.window = Some(TrimmedWindow::new(size, trim));
+        self
+    }
+
+    /// Do not update the quantile estimators, the mean is reported in place of the median
+    pub fn with_approx_median(mut self) -> Self {
+        self.approx_median = true;
+        self
+    }
+
+    /// Track an additional quantile `q`, reported under `label`
+    pub fn with_quantile(mut self, label: String, q: T) -> Self {
+        self.percentiles.push((label, q, Quantile::new(q).unwrap()));
+        self
+    }
+
     pub fn update(&mut self, val: T) {
-        self.mean.update(val);
-        self.median.update(val);
-        self.q1.update(val);
-        self.q3.update(val);
-        self.variance.update(val);
-        self.count += 1;
+        self.update_weighted(val, 1);
+    }
+
+    /// Same as `weight` calls to `update`, in a single step for every estimator
+    /// but the streaming quantiles, which have no weighted update and see the
+    /// value repeated at most `P2_REPEAT_CAP` times
+    pub fn update_weighted(&mut self, val: T, weight: u64) {
+        if weight == 0 {
+            return;
+        }
+        // Only the values that are counted, which under --range-only are the finite ones
+        if let Some(checksum) = self
+            .checksum
+            .as_mut()
+            .filter(|_| !self.range_only || val.is_finite())
+        {
+            checksum.update_weighted(val, weight);
+        }
+        if self.range_only {
+            if val.is_finite() {
+                self.count += weight;
+                self.min = self.min.min(val);
+                self.max = self.max.max(val);
+                self.initialized = true;
+            }
+            return;
+        }
+
+        // Against the mean of the previous values, so that a value never pulls
+        // the mean it is compared to towards itself. The first one has no deviation.
+        // After k copies of the value the deviation of the next one shrinks to
+        // n / (n + k) of the deviation from the mean of the n values before the chunk
+        if self.count > 0 {
+            let deviation = (val - self.mean()).abs().to_f64().unwrap();
+            let shrink = harmonic_span(self.count - 1, self.count + weight - 1);
+            self.mad_mean.update(deviation * self.count as f64 * shrink);
+        }
+        self.count += weight;
+        self.sum.update(val.to_f64().unwrap() * weight as f64);
+        if !self.approx_median {
+            for _ in 0..weight.min(P2_REPEAT_CAP) {
+                self.median.update(val);
+                self.q1.update(val);
+                self.q3.update(val);
+                for (_, _, quantile) in self.percentiles.iter_mut() {
+                    quantile.update(val);
+                }
+            }
+        }
+        if let Some(exact) = self.exact.as_mut() {
+            if !exact.push_repeated(val, weight) {
+                self.exact = None;
+                self.exact_overflowed = true;
+            }
+        }
+        // Never weighted, --sorted conflicting with --weighted-counts
+        if let Some(sorted) = self.sorted.as_mut() {
+            self.unsorted |= !sorted.push(val);
+        }
+        self.variance.update(val, weight);
         self.min = self.min.min(val);
         self.max = self.max.max(val);
+        if let Some(window) = self.window.as_mut() {
+            window.update_repeated(val, weight);
+        }
+        if let Some(extremes) = self.extremes.as_mut() {
+            extremes.update_repeated(val, weight);
+        }
+        if let Some((counts, _)) = self.value_counts.as_mut() {
+            counts.update(val, weight as usize);
+        }
+        if let Some(sample) = self.sample.as_mut() {
+            sample.update_repeated(val, weight);
+        }
+        if let Some(target) = self.target.as_mut() {
+            target.update(val, weight);
+        }
+        if let Some(extended) = self.extended.as_mut() {
+            extended.update(val, weight);
+        }
         self.initialized = true;
     }
 
-    pub fn to_json(&self) -> Value {
-        json!({
-            "mean": self.mean.get(),
-            "variance": self.variance.get(),
-            "median": self.median.get(),
-            "q1": self.q1.get(),
-            "q3": self.q3.get(),
-            "count": self.count,
-            "min": self.min,
-            "max": self.max,
-        })
+    /// Range and interquartile range of the values seen so far, the IQR is
+    /// only known when quantiles are estimated
+    pub fn spread(&self) -> (Option<T>, Option<T>) {
+        if !self.initialized {
+            return (None, None);
+        }
+        let range = self.max - self.min;
+        let iqr = (!self.approx_median && !self.range_only).then(|| self.q3.get() - self.q1.get());
+        (Some(range), iqr)
+    }
+
+    /// Mean from the compensated sum, which unlike a running mean doesn't drift
+    /// over long streams, or lose updates once a single precision count saturates
+    fn mean(&self) -> T {
+        T::from_f64(self.sum.get() / self.count as f64).unwrap()
+    }
+
+    /// Mean absolute deviation of each value from the mean of the values before it
+    fn mad_mean(&self) -> T {
+        T::from_f64(self.mad_mean.get() / self.count as f64).unwrap()
     }
 
-    fn stub(&self) -> String {
-        let mut s = "".to_owned();
+    /// z-score of `val` against the values seen so far, if their spread is known
+    pub fn zscore(&self, val: T) -> Option<T> {
+        let std = self.variance.get::<T>().sqrt();
+        if self.count < 2 || std <= T::zero() {
+            return None;
+        }
+        Some((val - self.mean()) / std)
+    }
+
+    fn push_checksum(&self, summary: &mut Summary<T>) {
+        if let Some(checksum) = self.checksum.as_ref() {
+            summary.push("Checksum", "checksum", Metric::Text(checksum.hex()));
+        }
+    }
+
+    pub fn summary(&self) -> Summary<T> {
+        let mut summary = Summary::default();
+
+        if self.range_only {
+            let range = if self.initialized {
+                Metric::Float(self.max - self.min)
+            } else {
+                Metric::NA
+            };
+            summary.push("Min", "min", Metric::Float(self.min));
+            summary.push("Max", "max", Metric::Float(self.max));
+            summary.push("Range", "range", range);
+            summary.push("Count", "count", Metric::Count(self.count));
+            self.push_checksum(&mut summary);
+            return summary;
+        }
+
+        // The estimators have no value before the first update
+        if self.initialized && self.approx_median {
+            let (mean, variance) = (self.mean(), self.variance.get());
+            summary.push("Mean", "mean", Metric::Float(mean));
+            summary.push("Variance", "variance", Metric::Float(variance));
+            summary.push("MAD from mean", "mad_mean", Metric::Float(self.mad_mean()));
+            summary.push(
+                "Dispersion index",
+                "dispersion_index",
+                dispersion_index(variance, mean),
+            );
+            summary.push(
+                "Median (approx. by mean)",
+                "approx_median",
+                Metric::Float(mean),
+            );
+            // Kept so that every mode reports the same keys, the median itself being unknown
+            summary.push("Median", "median", Metric::NA);
+            summary.push("q1", "q1", Metric::NA);
+            summary.push("q3", "q3", Metric::NA);
+        } else if self.initialized {
+            // Exact or sample quantiles once finalized, streaming estimates otherwise
+            let exact = self.exact.as_ref();
+            let quantile = |streaming: &Quantile<T>, q: f32| {
+                let sorted = self.sorted_quantile(T::from_f32(q).unwrap());
+                Metric::Float(sorted.unwrap_or_else(|| streaming.get()))
+            };
+
+            let mean = self.mean();
+            let variance = exact
+                .and_then(|e| e.variance())
+                .unwrap_or_else(|| self.variance.get());
 
-        s += "Mean:\tNA\n";
-        s += "Variance:\tNA\n";
-        s += "Median:\tNA\n";
-        s += "q1:\tNA\n";
-        s += "q3:\tNA\n";
-        s += &format!("Count:\t{}\n", self.count);
-        s += &format!("Min:\t{}\n", self.min);
-        s += &format!("Max:\t{}", self.max);
+            summary.push("Mean", "mean", Metric::Float(mean));
+            summary.push("Variance", "variance", Metric::Float(variance));
+            let mad_mean = exact.and_then(|e| e.mad_mean());
+            summary.push(
+                "MAD from mean",
+                "mad_mean",
+                Metric::Float(mad_mean.unwrap_or_else(|| self.mad_mean())),
+            );
+            summary.push(
+                "Dispersion index",
+                "dispersion_index",
+                dispersion_index(variance, mean),
+            );
+            summary.push("Median", "median", quantile(&self.median, 0.5));
+            summary.push("q1", "q1", quantile(&self.q1, 0.25));
+            summary.push("q3", "q3", quantile(&self.q3, 0.75));
+            for (label, q, streaming) in self.percentiles.iter() {
+                let value = self.sorted_quantile(*q).unwrap_or_else(|| streaming.get());
+                summary.push(label, label, Metric::Float(value));
+            }
+        } else {
+            summary.push("Mean", "mean", Metric::NA);
+            summary.push("Variance", "variance", Metric::NA);
+            summary.push("MAD from mean", "mad_mean", Metric::NA);
+            summary.push("Dispersion index", "dispersion_index", Metric::NA);
+            summary.push("Median", "median", Metric::NA);
+            summary.push("q1", "q1", Metric::NA);
+            summary.push("q3", "q3", Metric::NA);
+            for (label, _, _) in self.percentiles.iter() {
+                summary.push(label, label, Metric::NA);
+            }
+        }
+        summary.push("Count", "count", Metric::Count(self.count));
+        self.push_checksum(&mut summary);
+        summary.push(
+            "Sum",
+            "sum",
+            Metric::Float(T::from_f64(self.sum.get()).unwrap()),
+        );
+        summary.push("Min", "min", Metric::Float(self.min));
+        summary.push("Max", "max", Metric::Float(self.max));
+        let compared = self
+            .exact
+            .as_ref()
+            .filter(|_| self.percentile_error && self.initialized);
+        if let Some(exact) = compared {
+            for (label, key, q, streaming) in self.quantiles() {
+                let Some(exact) = exact.quantile(q) else {
+                    continue;
+                };
+                let streaming = streaming.get();
+                let error = (streaming - exact).abs();
+                let relative = if exact == T::zero() {
+                    Metric::NA
+                } else {
+                    Metric::Float(error / exact.abs())
+                };
+                summary.push(
+                    &format!("{label} streaming"),
+                    &format!("{key}_streaming"),
+                    Metric::Float(streaming),
+                );
+                summary.push(
+                    &format!("{label} abs error"),
+                    &format!("{key}_abs_error"),
+                    Metric::Float(error),
+                );
+                summary.push(
+                    &format!("{label} rel error"),
+                    &format!("{key}_rel_error"),
+                    relative,
+                );
+            }
+        }
+        let sorted = self.exact.as_ref().and_then(|e| e.sorted());
+        if let (Some((bins, prominence)), Some(sorted)) = (self.modes, sorted) {
+            let values: Vec<f64> = sorted
+                .iter()
+                .map(|v| v.to_f64().unwrap())
+                .filter(|v| v.is_finite())
+                .collect();
+            if let Some(modes) = modes::detect_modes(&values, bins, prominence) {
+                let locations = modes.locations.iter().map(|&l| T::from_f64(l).unwrap());
+                summary.push(
+                    "Modes",
+                    "modes",
+                    Metric::Count(modes.locations.len() as u64),
+                );
+                summary.push(
+                    "Mode locations",
+                    "mode_locations",
+                    Metric::List(locations.collect()),
+                );
+                summary.push("Mode bins", "mode_bins", Metric::Count(modes.bins as u64));
+                summary.push(
+                    "Mode prominence",
+                    "mode_prominence",
+                    Metric::Float(T::from_f64(prominence).unwrap()),
+                );
+            }
+        }
+        if let (Some(bootstrap), Some(sorted)) = (&self.bootstrap, sorted) {
+            let quantiles = self.quantiles();
+            let levels: Vec<T> = quantiles.iter().map(|(_, _, q, _)| *q).collect();
+            let intervals = bootstrap.quantile_intervals(sorted, &levels);
+            for ((label, key, _, _), interval) in quantiles.into_iter().zip(intervals) {
+                let (low, high) = interval.map_or((Metric::NA, Metric::NA), |(low, high)| {
+                    (Metric::Float(low), Metric::Float(high))
+                });
+                summary.push(&format!("{label} CI low"), &format!("{key}_ci_low"), low);
+                summary.push(&format!("{label} CI high"), &format!("{key}_ci_high"), high);
+            }
+        }
+        for query in self.queries.iter() {
+            let answer = sorted.and_then(|sorted| query.answer(sorted));
+            summary.push(
+                &query.label(),
+                &query.key(),
+                answer.map_or(Metric::NA, Metric::Float),
+            );
+        }
+        if !self.cdf.is_empty() {
+            let points: Option<Vec<(T, T)>> = self
+                .cdf
+                .iter()
+                .map(|&x| Some((T::from_f64(x)?, Query::Rank(x).answer(sorted?)?)))
+                .collect();
+            summary.push("CDF", "cdf", points.map_or(Metric::NA, Metric::Cdf));
+        }
+        if self.exact_threshold {
+            summary.push(
+                "Exact quantiles",
+                "exact_quantiles",
+                Metric::Bool(self.exact.is_some()),
+            );
+        }
+        if let Some(sample) = &self.sample {
+            summary.push(
+                "Quantile sample size",
+                "quantile_sample_size",
+                Metric::Count(sample.len() as u64),
+            );
+        }
+        if let Some(extremes) = &self.extremes {
+            summary.push("Smallest", "smallest", Metric::List(extremes.smallest()));
+            summary.push("Largest", "largest", Metric::List(extremes.largest()));
+        }
+        if let Some((counts, n)) = &self.value_counts {
+            summary.push(
+                "Distinct values",
+                "distinct_values",
+                Metric::Count(counts.distinct() as u64),
+            );
+            summary.push(
+                "Value counts",
+                "value_counts",
+                Metric::Frequencies(counts.top(*n)),
+            );
+        }
+        if let Some(window) = &self.window {
+            let trimmed = window.trimmed_mean().map_or(Metric::NA, Metric::Float);
+            summary.push("Windowed trimmed mean", "windowed_trimmed_mean", trimmed);
+        }
+        if let Some(extended) = &self.extended {
+            let metric = |v: f64| {
+                if self.initialized {
+                    Metric::Float(T::from_f64(v).unwrap())
+                } else {
+                    Metric::NA
+                }
+            };
+            summary.push("Product", "product", metric(extended.product()));
+            summary.push(
+                "Log abs product",
+                "log_abs_product",
+                metric(extended.log_abs_product()),
+            );
+            summary.push(
+                "Sum of squares",
+                "sum_of_squares",
+                metric(extended.sum_of_squares()),
+            );
+        }
+        if let Some(target) = &self.target {
+            let metric = |v: T| {
+                if self.initialized {
+                    Metric::Float(v)
+                } else {
+                    Metric::NA
+                }
+            };
+            summary.push("MAE", "mae", metric(target.mae()));
+            summary.push("RMSE", "rmse", metric(target.rmse()));
+            summary.push("Bias", "bias", metric(target.bias()));
+        }
+        let sorted = self
+            .exact
+            .as_ref()
+            .and_then(|e| e.sorted())
+            .filter(|_| self.fit);
+        if let Some(sorted) = sorted {
+            let values: Vec<f64> = sorted.iter().map(|v| v.to_f64().unwrap()).collect();
+            let fits = fit::fit_all(&values);
+            let best = fits.first().map(|f| f.name.to_owned());
+            summary.push(
+                "Best fit",
+                "best_fit",
+                best.map_or(Metric::NA, Metric::Text),
+            );
+            for fit in fits {
+                for (param, value) in fit.params {
+                    summary.push(
+                        &format!("{} {param}", fit.name),
+                        &format!("{}_{param}", fit.name.replace('-', "_")),
+                        Metric::Float(T::from_f64(value).unwrap()),
+                    );
+                }
+                summary.push(
+                    &format!("{} KS", fit.name),
+                    &format!("{}_ks", fit.name.replace('-', "_")),
+                    Metric::Float(T::from_f64(fit.ks).unwrap()),
+                );
+            }
+        }
 
-        s
+        summary
     }
 }
 
-impl<T> Display for Stats<T>
+/// Counts of values that were read but not added to the statistics as is
+#[derive(Clone, Default)]
+struct Dropped {
+    // Parsed values ignored by --drop-first
+    warmup: u64,
+    infinite: u64,
+    out_of_range: u64,
+    long_lines: u64,
+    bad: u64,
+    duplicates: u64,
+    // Added as 0 by --nan-as-zero
+    nan_coerced: u64,
+}
+
+impl Dropped {
+    /// Values dropped since the counts were `earlier`
+    fn since(&self, earlier: &Dropped) -> Dropped {
+        Dropped {
+            warmup: self.warmup - earlier.warmup,
+            infinite: self.infinite - earlier.infinite,
+            out_of_range: self.out_of_range - earlier.out_of_range,
+            long_lines: self.long_lines - earlier.long_lines,
+            bad: self.bad - earlier.bad,
+            duplicates: self.duplicates - earlier.duplicates,
+            nan_coerced: self.nan_coerced - earlier.nan_coerced,
+        }
+    }
+}
+
+/// Summary of `stats` with the rows of the options that changed their input, the
+/// dropped counts being NA when no `dropped` values can be attributed to them
+fn summarize<T>(
+    cli: &Cli,
+    label: Option<&str>,
+    stats: &Stats<T>,
+    dropped: Option<&Dropped>,
+) -> Summary<T>
 where
-    T: Float + FromPrimitive + AddAssign + SubAssign + Display + Debug + Serialize + FromStr,
+    T: Float + FromPrimitive + AddAssign + SubAssign + Display + Serialize,
 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.initialized {
-            let mut s = "".to_owned();
-
-            s += &format!("Mean:\t{}\n", self.mean.get());
-            s += &format!("Variance:\t{}\n", self.variance.get());
-            s += &format!("Median:\t{}\n", self.median.get());
-            s += &format!("q1:\t{}\n", self.q1.get());
-            s += &format!("q3:\t{}\n", self.q3.get());
-            s += &format!("Count:\t{}\n", self.count);
-            s += &format!("Min:\t{}\n", self.min);
-            s += &format!("Max:\t{}", self.max);
-
-            writeln!(f, "{}", s)
+    let count =
+        |count: fn(&Dropped) -> u64| dropped.map_or(Metric::NA, |d| Metric::Count(count(d)));
+    let mut summary = stats.summary();
+    if let Some(label) = label {
+        summary.prepend("Label", "label", Metric::Text(label.to_owned()));
+    }
+    if let Some(shift) = cli.shift {
+        summary.push("Shift", "shift", Metric::Float(T::from_f64(shift).unwrap()));
+    }
+    if cli.drop_first > 0 {
+        summary.push("Warmup dropped", "warmup_dropped", count(|d| d.warmup));
+    }
+    if cli.finite_only {
+        summary.push(
+            "Infinite dropped",
+            "infinite_dropped",
+            count(|d| d.infinite),
+        );
+    }
+    if cli.tolerant {
+        summary.push("Bad values skipped", "bad_skipped", count(|d| d.bad));
+    }
+    if cli.nan_as_zero {
+        summary.push(
+            "NaN coerced to zero",
+            "nan_coerced",
+            count(|d| d.nan_coerced),
+        );
+    }
+    if cli.distinct {
+        summary.push(
+            "Raw count",
+            "raw_count",
+            dropped.map_or(Metric::NA, |d| Metric::Count(stats.count + d.duplicates)),
+        );
+        summary.push(
+            "Duplicates dropped",
+            "duplicates_dropped",
+            count(|d| d.duplicates),
+        );
+    }
+    if cli.skip_long_lines {
+        summary.push(
+            "Long lines skipped",
+            "long_lines_skipped",
+            count(|d| d.long_lines),
+        );
+    }
+    if cli.clamp_range.is_some() {
+        let (label, key) = if cli.clip {
+            ("Out of range clipped", "out_of_range_clipped")
         } else {
-            writeln!(f, "{}", self.stub())
+            ("Out of range dropped", "out_of_range_dropped")
+        };
+        summary.push(label, key, count(|d| d.out_of_range));
+    }
+
+    summary
+}
+
+/// Summary shown by the running display, with the live spread of the values
+fn running_summary<T>(
+    cli: &Cli,
+    label: Option<&str>,
+    stats: &Stats<T>,
+    dropped: &Dropped,
+) -> Summary<T>
+where
+    T: Float + FromPrimitive + AddAssign + SubAssign + Display + Serialize,
+{
+    let mut summary = summarize(cli, label, stats, Some(dropped));
+    // Range-only summaries already have their range
+    if !cli.range_only {
+        let (range, iqr) = stats.spread();
+        summary.push("Range", "range", range.map_or(Metric::NA, Metric::Float));
+        summary.push("IQR", "iqr", iqr.map_or(Metric::NA, Metric::Float));
+    }
+    summary
+}
+
+/// Text of the running display, recording the sparkline metric if one is drawn
+fn running_text<T>(
+    cli: &Cli,
+    label: Option<&str>,
+    stats: &Stats<T>,
+    dropped: &Dropped,
+    sparkline: Option<&mut Sparkline>,
+    style: &Style,
+) -> String
+where
+    T: Float + FromPrimitive + AddAssign + SubAssign + Display + LowerExp + Serialize,
+{
+    let mut summary = running_summary(cli, label, stats, dropped);
+    if let (Some(sparkline), Some(key)) = (sparkline, cli.sparkline.as_ref()) {
+        if let Some(val) = summary.get(key).and_then(Metric::as_f64) {
+            sparkline.update(val);
         }
+        summary.push("Sparkline", "sparkline", Metric::Text(sparkline.render()));
     }
+    summary.render(style)
 }
 
-pub fn compute_stats<T>(json: bool, running: bool, polling: usize, skip_header: bool) -> Result<()>
+/// Final summary of a segment of the input, with the values it dropped
+fn segment_summary<T>(cli: &Cli, mut stats: Stats<T>, index: u64, dropped: &Dropped) -> Summary<T>
 where
-    T: Float + FromPrimitive + AddAssign + SubAssign + Display + Debug + Serialize + FromStr,
+    T: Float + FromPrimitive + AddAssign + SubAssign + Display + Serialize,
+{
+    stats.finalize();
+    summarize(
+        cli,
+        Some(&format!("segment {index}")),
+        &stats,
+        Some(dropped),
+    )
+}
+
+/// Seed of random sampling, unseeded runs draw different samples each time
+fn seed(cli: &Cli) -> u64 {
+    cli.seed.unwrap_or_else(|| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH);
+        now.map_or(0, |d| d.as_nanos() as u64)
+    })
+}
+
+/// Requested tail percentiles in increasing order, without repeats. The
+/// shortest representation of each is used in its label, so that 99.9 is
+/// reported as 'p99.9' and 99.99 as 'p99.99'
+fn tail_percentiles(cli: &Cli) -> Vec<f64> {
+    let mut percentiles = cli.tail_percentiles.clone();
+    percentiles.sort_by(f64::total_cmp);
+    percentiles.dedup();
+    percentiles
+}
+
+fn new_stats<T>(cli: &Cli) -> Stats<T>
+where
+    T: Float + FromPrimitive + AddAssign + SubAssign + Display + Serialize,
 {
-    let mut stderr = io::stderr();
     let mut stats = Stats::default();
+    for p in tail_percentiles(cli) {
+        stats = stats.with_quantile(format!("p{p}"), T::from_f64(p / 100.0).unwrap());
+    }
+    if cli.approx_median {
+        stats = stats.with_approx_median();
+    }
+    if let Some(size) = cli.window {
+        stats = stats.with_trimmed_window(size.get(), cli.trim);
+    }
+    if cli.exact {
+        stats = stats.with_exact(cli.exact_cap);
+    }
+    if let Some(n) = cli.exact_threshold {
+        stats = stats.with_exact_threshold(n.get());
+    }
+    if cli.sorted {
+        stats = stats.with_sorted();
+    }
+    if cli.range_only {
+        stats = stats.with_range_only();
+    }
+    if cli.percentile_error {
+        stats = stats.with_percentile_error();
+    }
+    if let Some(k) = cli.k_extremes {
+        stats = stats.with_extremes(k.get());
+    }
+    if let Some(n) = cli.value_counts {
+        stats = stats.with_value_counts(n.get(), cli.max_distinct);
+    }
+    if cli.checksum {
+        stats = stats.with_checksum();
+    }
+    if cli.fit {
+        stats = stats.with_fit();
+    }
+    if !cli.query.is_empty() {
+        stats = stats.with_queries(cli.query.clone());
+    }
+    if !cli.cdf.is_empty() {
+        stats = stats.with_cdf(cli.cdf.clone());
+    }
+    if cli.extended {
+        stats = stats.with_extended();
+    }
+    if let Some(size) = cli.sample_quantiles {
+        stats = stats.with_sample_quantiles(size.get(), seed(cli));
+    }
+    if cli.detect_modes {
+        stats = stats.with_modes(cli.mode_bins.map(|b| b.get()), cli.mode_prominence);
+    }
+    if cli.quantile_ci {
+        stats = stats.with_quantile_intervals(Bootstrap {
+            resamples: cli.bootstrap.get(),
+            confidence: cli.confidence,
+            seed: seed(cli),
+        });
+    }
+    if let Some(target) = cli.target {
+        stats = stats.with_target(T::from_f64(target).unwrap());
+    }
+
+    stats
+}
 
-    let running_print_height = 9;
+/// Lines of an input stream, along with its file name (`None` for stdin)
+/// and header line when one is expected
+struct Input {
+    name: Option<String>,
+    header: Option<String>,
+    // Lines with the byte offset of their start
+    lines: Box<dyn Iterator<Item = io::Result<(u64, Line)>> + Send>,
+}
+
+/// Open every input file up front so that missing files are reported
+/// before any reading, stdin is used if there are none
+fn open_inputs(cli: &Cli) -> Result<Vec<Input>> {
+    let mut readers: Vec<(Option<String>, Box<dyn BufRead + Send>)> = vec![];
+    if cli.files.is_empty() {
+        let stdin = BufReader::with_capacity(cli.buffer_size, io::stdin());
+        readers.push((None, Box::new(stdin)));
+    }
+    for path in cli.files.iter() {
+        let file =
+            File::open(path).with_context(|| format!("Could not open '{}'", path.display()))?;
+        readers.push((
+            Some(path.display().to_string()),
+            Box::new(BufReader::with_capacity(cli.buffer_size, file)),
+        ));
+    }
+
+    readers
+        .into_iter()
+        .enumerate()
+        .map(|(index, (name, reader))| {
+            if cli.json_array {
+                let lines = JsonArrayReader::new(reader, cli.json_field.clone());
+                return Ok(Input {
+                    name,
+                    header: None,
+                    lines: Box::new(lines),
+                });
+            }
+
+            let mut lines = LineReader::new(reader, cli.max_line_length);
+            // Inputs are read as one stream, following ones have no header
+            let header = if (cli.header || cli.skip_header) && index == 0 {
+                match lines.next().transpose()? {
+                    Some((_, Line::Text(header))) => Some(header),
+                    Some((_, Line::TooLong)) => {
+                        bail!("Header line is longer than --max-line-length")
+                    }
+                    None => None,
+                }
+            } else {
+                None
+            };
+            Ok(Input {
+                name,
+                header,
+                lines: Box::new(lines),
+            })
+        })
+        .collect()
+}
+
+/// Text style of output written to a stream, `terminal` telling if it is a tty
+fn style(cli: &Cli, terminal: bool) -> Style {
+    Style {
+        notation: cli.notation,
+        color: cli.color.enabled(terminal),
+        percentile_digits: cli.percentile_digits,
+        width: None,
+        na: cli.na_token.clone(),
+    }
+}
+
+/// Whether the running display is drawn to a terminal
+fn display_terminal(cli: &Cli) -> bool {
+    match cli.display_to {
+        DisplayTarget::Stderr => io::stderr().is_terminal(),
+        DisplayTarget::Stdout => io::stdout().is_terminal(),
+        // Paths are meant to be terminals
+        DisplayTarget::Path(_) => true,
+    }
+}
+
+/// Whether the --sparkline is drawn, only under a running display to a terminal
+fn sparkline_drawn(cli: &Cli) -> bool {
+    cli.sparkline.is_some() && !cli.hide_running && display_terminal(cli)
+}
+
+fn style_for_display(cli: &Cli) -> Style {
+    let terminal = display_terminal(cli);
+    Style {
+        width: display_width(cli, terminal),
+        ..style(cli, terminal)
+    }
+}
+
+/// Columns the running display must fit in, `None` when it may wrap
+fn display_width(cli: &Cli, terminal: bool) -> Option<usize> {
+    match cli.display_width {
+        Some(0) => None,
+        Some(width) => Some(width.into()),
+        None if terminal => crossterm::terminal::size()
+            .ok()
+            .map(|(width, _)| width.into()),
+        None => None,
+    }
+}
+
+/// Output format of the final summaries, the json flags take precedence over --format
+fn output_format(cli: &Cli) -> OutputFormat {
+    if cli.json_pretty {
+        OutputFormat::JsonPretty
+    } else if cli.json || cli.ndjson_stream {
+        OutputFormat::Json
+    } else {
+        cli.format
+    }
+}
+
+/// Every json key that can be reported, except those named after
+/// --tail-percentiles levels, filled with null under --stable-schema
+const SCHEMA_KEYS: &[&str] = &[
+    "label",
+    "mean",
+    "variance",
+    "mad_mean",
+    "dispersion_index",
+    "median",
+    "approx_median",
+    "q1",
+    "q3",
+    "count",
+    "checksum",
+    "sum",
+    "min",
+    "max",
+    "range",
+    "median_streaming",
+    "median_abs_error",
+    "median_rel_error",
+    "q1_streaming",
+    "q1_abs_error",
+    "q1_rel_error",
+    "q3_streaming",
+    "q3_abs_error",
+    "q3_rel_error",
+    "modes",
+    "mode_locations",
+    "mode_bins",
+    "mode_prominence",
+    "median_ci_low",
+    "median_ci_high",
+    "q1_ci_low",
+    "q1_ci_high",
+    "q3_ci_low",
+    "q3_ci_high",
+    "smallest",
+    "largest",
+    "distinct_values",
+    "value_counts",
+    "windowed_trimmed_mean",
+    "cdf",
+    "exact_quantiles",
+    "quantile_sample_size",
+    "product",
+    "log_abs_product",
+    "sum_of_squares",
+    "mae",
+    "rmse",
+    "bias",
+    "best_fit",
+    "normal_mu",
+    "normal_sigma",
+    "normal_ks",
+    "log_normal_mu",
+    "log_normal_sigma",
+    "log_normal_ks",
+    "exponential_lambda",
+    "exponential_ks",
+    "shift",
+    "warmup_dropped",
+    "infinite_dropped",
+    "bad_skipped",
+    "nan_coerced",
+    "raw_count",
+    "duplicates_dropped",
+    "long_lines_skipped",
+    "out_of_range_dropped",
+    "out_of_range_clipped",
+    "lines",
+    "partial",
+];
+
+fn summary_json<T: Display + Serialize>(cli: &Cli, summary: &Summary<T>) -> serde_json::Value {
+    let mut json = summary.to_json();
+    if let (true, Some(object)) = (cli.bignum_strings, json.as_object_mut()) {
+        // Counts are the only integers, floats are serialized with a fraction. Keep
+        // the list in the --bignum-strings doc in sync with the Metric::Count keys
+        for value in object.values_mut() {
+            if let Some(count) = value.as_u64() {
+                *value = serde_json::Value::String(count.to_string());
+            }
+        }
+    }
+    if let (true, Some(object)) = (cli.stable_schema, json.as_object_mut()) {
+        for key in SCHEMA_KEYS {
+            object
+                .entry(key.to_string())
+                .or_insert(serde_json::Value::Null);
+        }
+    }
+    json
+}
+
+fn print_summaries<T>(cli: &Cli, out: &mut dyn Write, summaries: &[Summary<T>]) -> Result<()>
+where
+    T: Float + Display + LowerExp + Serialize,
+{
+    let style = style(cli, io::stdout().is_terminal());
+    match output_format(cli) {
+        OutputFormat::Html if cli.html_standalone => {
+            writeln!(out, "{}", html_document(&html_table(summaries, &style)))?
+        }
+        OutputFormat::Html => writeln!(out, "{}", html_table(summaries, &style))?,
+        format => {
+            for summary in summaries {
+                match format {
+                    OutputFormat::JsonPretty => writeln!(
+                        out,
+                        "{}",
+                        serde_json::to_string_pretty(&summary_json(cli, summary))?
+                    )?,
+                    OutputFormat::Json => writeln!(out, "{}", summary_json(cli, summary))?,
+                    _ => match &cli.template {
+                        Some(template) => writeln!(
+                            out,
+                            "{}",
+                            template.render(&summary.values(&style), &style.na)
+                        )?,
+                        None => writeln!(out, "{}", summary.render(&style))?,
+                    },
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Write `points` equally spaced percentiles of the exact values as csv rows
+fn write_cdf<T>(out: &mut dyn Write, stats: &Stats<T>, points: usize, style: &Style) -> Result<()>
+where
+    T: Float + FromPrimitive + AddAssign + SubAssign + Display + LowerExp,
+{
+    let Some(exact) = stats.exact.as_ref() else {
+        bail!("--cdf-points needs every value but the --exact buffer is full");
+    };
+
+    writeln!(out, "percentile,value")?;
+    for i in 1..=points {
+        let q = i as f64 / points as f64;
+        let Some(value) = exact.quantile(T::from_f64(q).unwrap()) else {
+            break;
+        };
+        writeln!(out, "{},{}", q * 100.0, style.float(value))?;
+    }
+
+    Ok(())
+}
+
+/// Replace the contents of `path` at once, by writing a temporary file next
+/// to it and renaming it, so readers never see a partial write
+fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let context = || format!("Could not write to '{}'", path.display());
+    std::fs::write(&tmp, contents).with_context(context)?;
+    std::fs::rename(&tmp, path).with_context(context)?;
+    Ok(())
+}
+
+/// Write each metric of `summary` to a file of `dir` named after its json key
+fn write_split<T>(dir: &Path, summary: &Summary<T>, style: &Style) -> Result<()>
+where
+    T: Float + Display + LowerExp + Serialize,
+{
+    let context = || format!("Could not write to '{}'", dir.display());
+    std::fs::create_dir_all(dir).with_context(context)?;
+    // Plain values, whatever the terminal
+    let style = Style {
+        color: false,
+        ..style.clone()
+    };
+    for (key, value) in summary.values(&style) {
+        std::fs::write(dir.join(key), format!("{value}\n")).with_context(context)?;
+    }
+    Ok(())
+}
+
+/// Summary the flags give for a small sample of values, or for no value at
+/// all, to know which metrics are reported before reading any input
+fn sample_summary<T>(cli: &Cli, with_values: bool) -> Summary<T>
+where
+    T: Float + FromPrimitive + AddAssign + SubAssign + Display + Serialize,
+{
+    let labelled = cli.label.is_some()
+        || cli.header
+        || cli.per_file
+        || cli.split_sign
+        || cli.segment_on.is_some()
+        || cli.files.len() == 1;
+    let mut stats = new_stats::<T>(cli);
+    if with_values {
+        for i in 1..=16 {
+            stats.update(T::from_i32(i).unwrap());
+        }
+    }
+    stats.finalize();
+    let mut summary = summarize(
+        cli,
+        labelled.then_some(""),
+        &stats,
+        Some(&Dropped::default()),
+    );
+    if cli.ndjson_stream {
+        summary.push("Lines", "lines", Metric::Count(0));
+    }
+    if cli.abort_after.is_some() {
+        summary.push("Partial", "partial", Metric::Bool(false));
+    }
+    summary
+}
+
+/// Allow null in addition to the values of `schema`
+fn nullable(schema: serde_json::Value) -> serde_json::Value {
+    let null = json!("null");
+    let types = &schema["type"];
+    if *types == null || types.as_array().is_some_and(|t| t.contains(&null)) {
+        return schema;
+    }
+    json!({"anyOf": [schema, {"type": "null"}]})
+}
+
+/// JSON Schema of the json summary records. The metrics are those of summaries
+/// of a small sample, the ones missing without any value are not required and
+/// the ones that are NA without any value are nullable
+fn output_schema<T>(cli: &Cli) -> serde_json::Value
+where
+    T: Float + FromPrimitive + AddAssign + SubAssign + Display + Serialize,
+{
+    let mut schema = sample_summary::<T>(cli, true).json_schema();
+    let empty = sample_summary::<T>(cli, false).json_schema();
+
+    let properties = schema["properties"].as_object_mut().unwrap();
+    for (key, property) in properties.iter_mut() {
+        if cli.bignum_strings && property["type"] == "integer" {
+            *property = json!({"type": "string", "pattern": "^[0-9]+$"});
+        }
+        let required = empty["required"].as_array().unwrap().contains(&json!(key));
+        let null_when_empty = empty["properties"][key]["type"] == "null";
+        if null_when_empty || (cli.stable_schema && !required) {
+            *property = nullable(property.take());
+        }
+    }
+    if cli.stable_schema {
+        for key in SCHEMA_KEYS {
+            properties
+                .entry(key.to_string())
+                .or_insert(json!({"type": "null"}));
+        }
+        schema["required"] = json!(properties.keys().collect::<Vec<_>>());
+    } else {
+        schema["required"] = empty["required"].clone();
+    }
+    schema["$schema"] = json!("https://json-schema.org/draft/2020-12/schema");
+    schema["title"] = json!("stats summary");
+
+    schema
+}
+
+/// Configuration after defaults, validation and resolution, e.g. of the label
+fn resolved_config<T>(cli: &Cli, inputs: &[Input], label: Option<&str>) -> serde_json::Value {
+    let name =
+        |v: &dyn Fn() -> Option<clap::builder::PossibleValue>| v().map(|v| v.get_name().to_owned());
+    let inputs: Vec<String> = inputs
+        .iter()
+        .map(|i| i.name.clone().unwrap_or_else(|| "stdin".to_owned()))
+        .collect();
+    let mut quantiles = vec![0.25, 0.5, 0.75];
+    quantiles.extend(tail_percentiles(cli).iter().map(|p| p / 100.0));
+    let mode = if cli.range_only {
+        "range-only"
+    } else if cli.approx_median {
+        "approx-median"
+    } else if cli.exact {
+        "exact"
+    } else if cli.exact_threshold.is_some() {
+        "exact-threshold"
+    } else if cli.sorted {
+        "sorted"
+    } else {
+        "streaming"
+    };
+
+    let input = json!({
+        "files": inputs,
+        "format": if cli.json_array { "json-array" } else { "lines" },
+        "json_field": cli.json_field,
+        "header": cli.header || cli.skip_header,
+        "max_line_length": cli.max_line_length,
+        "buffer_size": cli.buffer_size,
+        "skip_long_lines": cli.skip_long_lines,
+        "byte_offsets": cli.byte_offsets,
+        "strict": cli.strict,
+        "weighted_counts": cli.weighted_counts,
+        "unit_parse": cli.unit_parse,
+        "base_unit": cli.base_unit.as_ref().map(|u| u.to_string()),
+        "tolerant": cli.tolerant,
+        "max_bad_fraction": cli.max_bad_fraction,
+        "nan_as_zero": cli.nan_as_zero,
+        "missing_token": cli.missing_token,
+    });
+    let filters = json!({
+        "transform": cli.transform.as_ref().map(|e| e.to_string()),
+        "abs": cli.abs,
+        "shift": cli.shift,
+        "drop_first": cli.drop_first,
+        "finite_only": cli.finite_only,
+        "clamp_range": cli.clamp_range.map(|b| json!({"lo": b.lo, "hi": b.hi, "clip": cli.clip})),
+        "max_distinct": (cli.distinct || cli.value_counts.is_some()).then_some(cli.max_distinct),
+    });
+    let estimators = json!({
+        "mode": mode,
+        "quantiles": if cli.range_only || cli.approx_median { vec![] } else { quantiles },
+        "exact_cap_bytes": cli.exact.then_some(cli.exact_cap),
+        "exact_threshold": cli.exact_threshold.map(|n| n.get()),
+        "sorted": cli.sorted,
+        "percentile_error": cli.percentile_error,
+        "fit": cli.fit,
+        "queries": cli.query.iter().map(Query::label).collect::<Vec<_>>(),
+        "cdf": cli.cdf,
+        "cdf_points": cli.cdf_points.map(|m| m.get()),
+        "sample_quantiles": cli.sample_quantiles.map(|n| n.get()),
+        "seed": cli.seed,
+        "detect_modes": cli.detect_modes.then(|| json!({"bins": cli.mode_bins.map(|b| b.get()), "prominence": cli.mode_prominence})),
+        "quantile_ci": cli.quantile_ci.then(|| json!({"resamples": cli.bootstrap.get(), "confidence": cli.confidence})),
+        "target": cli.target,
+        "extended": cli.extended,
+        "window": cli.window.map(|w| json!({"size": w.get(), "trim": cli.trim})),
+        "checksum": cli.checksum,
+        "k_extremes": cli.k_extremes.map(|k| k.get()),
+        "value_counts": cli.value_counts.map(|n| n.get()),
+        "outliers_out": cli.outliers_out.as_ref().map(|p| p.display().to_string()),
+        "zscore_threshold": cli.outliers_out.as_ref().map(|_| cli.zscore_threshold),
+        "robust_standardize": cli.robust_standardize,
+    });
+    let display = json!({
+        "running": !cli.hide_running,
+        "running_append": cli.running_append,
+        "to": match &cli.display_to {
+            DisplayTarget::Stderr => "stderr".to_owned(),
+            DisplayTarget::Stdout => "stdout".to_owned(),
+            DisplayTarget::Path(path) => path.display().to_string(),
+        },
+        // As detected for the running display, the same checks as the run itself
+        "terminal": display_terminal(cli),
+        "width": display_width(cli, display_terminal(cli)),
+        "sparkline": cli.sparkline.as_ref().filter(|_| sparkline_drawn(cli)),
+        "polling": if cli.poll_adaptive { json!("adaptive") } else { json!(cli.polling) },
+        "heartbeat_seconds": cli.heartbeat.map(|d| d.as_secs_f64()),
+        "timing": cli.timing,
+        "parse_stats": cli.parse_stats,
+        "debug_quantiles": cli.debug_quantiles,
+        "progress_file": cli.progress_file.as_ref().map(|p| p.display().to_string()),
+        "serve": cli.serve.map(|addr| addr.to_string()),
+        "abort_after_seconds": cli.abort_after.map(|d| d.as_secs_f64()),
+    });
+    let output = json!({
+        "label": label,
+        "format": name(&|| output_format(cli).to_possible_value()),
+        "html_standalone": cli.html_standalone,
+        "notation": name(&|| cli.notation.to_possible_value()),
+        "percentile_digits": cli.percentile_digits,
+        "na_token": cli.na_token,
+        "color": style(cli, io::stdout().is_terminal()).color,
+        "ndjson_stream": cli.ndjson_stream,
+        "line_buffered": cli.line_buffered,
+        "stable_schema": cli.stable_schema,
+        "template": cli.template.is_some(),
+        "bignum_strings": cli.bignum_strings,
+        "per_file": cli.per_file,
+        "segment_on": cli.segment_on,
+        "split_sign": cli.split_sign,
+        "split_dir": cli.split_dir.as_ref().map(|p| p.display().to_string()),
+        "baseline": cli.baseline.as_ref().map(|p| p.display().to_string()),
+        "tolerance": cli.baseline.as_ref().map(|_| cli.tolerance),
+    });
+
+    json!({
+        "float_type": std::any::type_name::<T>(),
+        "input": input,
+        "filters": filters,
+        "estimators": estimators,
+        "display": display,
+        "output": output,
+    })
+}
+
+/// Exit code after recovering from a panic with --recover-from-panic
+const PANIC_EXIT_CODE: i32 = 70;
+
+/// Most repeats of a weighted value fed to the streaming quantile estimators
+const P2_REPEAT_CAP: u64 = 1 << 16;
+
+/// Sum of 1 / i for i in (from, to], i.e. the difference of harmonic numbers H(to) - H(from)
+fn harmonic_span(from: u64, to: u64) -> f64 {
+    // Summed directly over short spans, which is also where the expansion is least precise
+    if to - from <= 1024 {
+        return (from + 1..=to).map(|i| 1.0 / i as f64).sum();
+    }
+    let harmonic = |n: u64| -> f64 {
+        if n < 64 {
+            return (1..=n).map(|i| 1.0 / i as f64).sum();
+        }
+        // Asymptotic expansion, the Euler-Mascheroni constant cancelling in the difference
+        let n = n as f64;
+        n.ln() + 1.0 / (2.0 * n) - 1.0 / (12.0 * n * n) + 1.0 / (120.0 * n.powi(4))
+    };
+    let euler = |n: u64| if n < 64 { 0.577_215_664_901_532_9 } else { 0.0 };
+    (harmonic(to) - euler(to)) - (harmonic(from) - euler(from))
+}
+
+fn compute_stats<T>(cli: &Cli) -> Result<()>
+where
+    T: Float
+        + FromPrimitive
+        + AddAssign
+        + SubAssign
+        + Display
+        + LowerExp
+        + Debug
+        + Serialize
+        + FromStr,
+{
+    if let Some(template) = &cli.template {
+        let summary = sample_summary::<T>(cli, true);
+        let keys: Vec<&str> = summary.keys().collect();
+        if let Some(unknown) = template.keys().find(|key| !keys.contains(key)) {
+            bail!(
+                "Unknown metric '{unknown}' in --template, known metrics are: {}",
+                keys.join(", ")
+            );
+        }
+    }
+    if let Some(key) = &cli.sparkline {
+        let mut stats = new_stats::<T>(cli);
+        stats.update(T::one());
+        let summary = running_summary(cli, None, &stats, &Dropped::default());
+        if summary.get(key).and_then(Metric::as_f64).is_none() {
+            let keys: Vec<&str> = summary
+                .keys()
+                .filter(|key| summary.get(key).and_then(Metric::as_f64).is_some())
+                .collect();
+            bail!(
+                "Unknown metric '{key}' in --sparkline, known numeric metrics are: {}",
+                keys.join(", ")
+            );
+        }
+    }
+
+    if cli.schema {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&output_schema::<T>(cli))?
+        );
+        return Ok(());
+    }
+
+    let running = !cli.hide_running;
+    let style = style(cli, io::stdout().is_terminal());
+    let display_style = style_for_display(cli);
+    let mut stderr = io::stderr();
+    let mut display = match running {
+        true if cli.running_append => Some(RunningDisplay::appending(cli.display_to.open()?)),
+        true => Some(RunningDisplay::new(cli.display_to.open()?)),
+        false => None,
+    };
+    // As wide as the display once past the label
+    let mut sparkline = cli
+        .sparkline
+        .as_ref()
+        .filter(|_| sparkline_drawn(cli))
+        .map(|_| {
+            let width = display_width(cli, true).unwrap_or(80);
+            Sparkline::new(width.saturating_sub(16).max(1))
+        });
+    let mut out: Box<dyn Write> = if cli.line_buffered {
+        Box::new(LineWriter::new(io::stdout()))
+    } else {
+        Box::new(BufWriter::new(io::stdout()))
+    };
+    let mut stats = new_stats::<T>(cli);
+    let mut dropped = Dropped::default();
+    let server = match cli.serve {
+        Some(addr) => {
+            Some(Server::bind(addr).with_context(|| format!("Could not listen on {addr}"))?)
+        }
+        None => None,
+    };
+    let mut robust = cli.robust_standardize.then(|| match cli.exact {
+        true => RobustScaler::exact(),
+        false => RobustScaler::streaming(),
+    });
+    let mut outliers = match &cli.outliers_out {
+        Some(path) => Some(LineWriter::new(File::create(path)?)),
+        None => None,
+    };
+    let threshold = T::from_f64(cli.zscore_threshold).unwrap();
+    let shift = cli.shift.map(|c| T::from_f64(c).unwrap());
+    let bounds = cli
+        .clamp_range
+        .map(|b| (T::from_f64(b.lo).unwrap(), T::from_f64(b.hi).unwrap()));
 
-    if running {
-        writeln!(stderr, "{}", stats)?;
+    let baseline = match &cli.baseline {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Could not read '{}'", path.display()))?;
+            let summary = baseline::last_summary(&contents)
+                .map_err(|e| anyhow!("Invalid baseline '{}': {e}", path.display()))?;
+            Some(summary)
+        }
+        None => None,
+    };
+
+    let inputs = open_inputs(cli)?;
+    // Per-file, split-sign and segmented runs label the total, otherwise the header
+    // or a single named file labels the output
+    let header = inputs.first().and_then(|input| input.header.clone());
+    let label = match (&cli.label, cli.files.as_slice()) {
+        (Some(label), _) => Some(label.clone()),
+        (None, _) if cli.per_file || cli.split_sign || cli.segment_on.is_some() => {
+            Some("combined".to_owned())
+        }
+        (None, _) if header.is_some() => header,
+        (None, [file]) => Some(file.display().to_string()),
+        (None, _) => None,
+    };
+    let label = label.as_deref();
+
+    if cli.show_config {
+        let config = resolved_config::<T>(cli, &inputs, label);
+        eprintln!("{}", serde_json::to_string_pretty(&config)?);
     }
 
-    let mut lines = io::stdin().lock().lines();
-    if skip_header {
-        lines.next();
+    // Appended snapshots start with the first values
+    if let Some(display) = display.as_mut().filter(|_| !cli.running_append) {
+        let sparkline = sparkline.as_mut();
+        display.draw(&running_text(
+            cli,
+            label,
+            &stats,
+            &dropped,
+            sparkline,
+            &display_style,
+        ))?;
     }
 
-    for (lineno, line) in lines.enumerate() {
-        let line = line?;
+    let mut redraw = if cli.poll_adaptive {
+        Redraw::adaptive(Duration::from_millis(200))
+    } else {
+        Redraw::every(cli.polling)
+    };
+    // Past 2^24 single precision floats can't represent every integer
+    let f32_limit = T::from_u32(1 << 24).unwrap();
+    let single_precision = size_of::<T>() == size_of::<f32>();
+    let mut precision_loss = false;
+    let mut distinct = cli.distinct.then(|| Distinct::new(cli.max_distinct));
+    let start = Instant::now();
+    let mut display_time = Duration::ZERO;
+    let mut parse_time = Duration::ZERO;
+    let mut bytes_read: u64 = 0;
+    let mut partial = false;
+    let mut last_heartbeat = Instant::now();
+    let mut lines_read: u64 = 0;
+    let mut file_summaries = vec![];
+    // Marker, index, statistics and dropped counts at the start of the current segment
+    let mut segment = cli
+        .segment_on
+        .as_deref()
+        .map(|marker| (marker.trim(), 1, new_stats::<T>(cli), Dropped::default()));
+    // Html tables hold every summary, and cdf points replace them
+    let emit_segments =
+        !matches!(output_format(cli), OutputFormat::Html) && cli.cdf_points.is_none();
+    // Negative then non-negative values
+    let mut sign_stats = cli
+        .split_sign
+        .then(|| [new_stats::<T>(cli), new_stats::<T>(cli)]);
+    for input in inputs {
+        let name = input.name;
+        let mut file_stats = cli.per_file.then(|| new_stats::<T>(cli));
+        let file_start = dropped.clone();
 
-        let num = match line.parse::<T>() {
-            Ok(v) => v,
-            Err(_) => {
-                bail!("Could not parse number on line {lineno}: '{line}'");
+        let unit = if cli.json_array { "element" } else { "line" };
+        let location_at = |lineno: usize, offset: u64, with_offset: bool| {
+            let offset = match with_offset {
+                true => format!(" (byte {offset})"),
+                false => String::new(),
+            };
+            match &name {
+                Some(name) => format!("{unit} {lineno} of {name}{offset}"),
+                None => format!("{unit} {lineno}{offset}"),
             }
         };
 
-        if running && lineno % polling == 0 {
-            stderr.execute(cursor::MoveUp(running_print_height))?;
-            stderr.execute(terminal::Clear(terminal::ClearType::FromCursorDown))?;
-            writeln!(stderr, "{}", stats)?;
+        // Read in the background when timed, so that deadlines and heartbeats
+        // are met while input stalls
+        let timed = cli.abort_after.is_some() || cli.heartbeat.is_some();
+        let lines: Box<dyn Iterator<Item = Option<_>>> = match timed {
+            true => Box::new(Ticking::spawn(input.lines, Duration::from_millis(50))),
+            false => Box::new(input.lines.map(Some)),
+        };
+        let mut next_lineno = 0;
+        // Lines read before the data, for the physical line numbers of --outliers-out
+        let header_lines = usize::from(input.header.is_some());
+        for line in lines {
+            if let Some(display) = display.as_mut().filter(|_| redraw.due(lines_read)) {
+                let drawing = Instant::now();
+                let sparkline = sparkline.as_mut();
+                let text = running_text(cli, label, &stats, &dropped, sparkline, &display_style);
+                let text = match cli.running_append {
+                    true => format!("--- line {lines_read} ---\n{text}"),
+                    false => text,
+                };
+                display.draw(&text)?;
+                display_time += drawing.elapsed();
+            }
+
+            if cli
+                .abort_after
+                .is_some_and(|budget| start.elapsed() >= budget)
+            {
+                partial = true;
+                break;
+            }
+
+            if let Some(interval) = cli.heartbeat {
+                if last_heartbeat.elapsed() >= interval {
+                    writeln!(stderr, "processed {lines_read} lines")?;
+                    last_heartbeat = Instant::now();
+                }
+            }
+
+            let Some(line) = line else {
+                continue;
+            };
+            let lineno = next_lineno;
+            next_lineno += 1;
+            lines_read += 1;
+
+            let (offset, line) = line?;
+            // Line numbers alone don't help much to find an error in huge lines
+            let location = |lineno| location_at(lineno, offset, cli.byte_offsets);
+            let line = match line {
+                Line::Text(line) => line,
+                Line::TooLong if cli.skip_long_lines => {
+                    dropped.long_lines += 1;
+                    continue;
+                }
+                Line::TooLong => {
+                    let max = cli.max_line_length.unwrap_or_default();
+                    let location = location_at(lineno, offset, true);
+                    bail!("{location} is longer than {max} bytes")
+                }
+            };
+
+            if let Some((_, index, stats, start)) = segment
+                .as_mut()
+                .filter(|(marker, ..)| line.trim() == *marker)
+            {
+                let finished = std::mem::replace(stats, new_stats::<T>(cli));
+                let mut summary = segment_summary(cli, finished, *index, &dropped.since(start));
+                *start = dropped.clone();
+                *index += 1;
+                if cli.abort_after.is_some() {
+                    summary.push("Partial", "partial", Metric::Bool(false));
+                }
+
+                if !emit_segments {
+                    file_summaries.push(summary);
+                    continue;
+                }
+                if let Some(display) = display.as_mut() {
+                    display.clear()?;
+                }
+                // Robust values go to stdout, and the summaries to stderr
+                let target: &mut dyn Write = match robust {
+                    Some(_) => &mut stderr,
+                    None => &mut out,
+                };
+                print_summaries(cli, target, &[summary])?;
+                target.flush()?;
+                continue;
+            }
+
+            bytes_read += line.len() as u64;
+            let parsing = cli.parse_stats.then(Instant::now);
+            let pair = match cli.weighted_counts {
+                true => parse::split_count(&line),
+                false => Some((line.as_str(), 1)),
+            };
+            let (text, weight) = match pair {
+                Some(pair) => pair,
+                None if cli.tolerant => {
+                    dropped.bad += 1;
+                    continue;
+                }
+                None => bail!("Expected 'value count' on {}: '{line}'", location(lineno)),
+            };
+            let missing = cli
+                .missing_token
+                .as_ref()
+                .is_some_and(|token| text.trim() == token);
+            let num = if missing {
+                Some(T::nan())
+            } else if cli.unit_parse {
+                match units::parse_with_unit::<T>(text, cli.base_unit.as_ref()) {
+                    Ok(v) => Some(v),
+                    Err(_) if cli.tolerant => None,
+                    Err(e) => bail!("{e} on {}: '{line}'", location(lineno)),
+                }
+            } else if cli.strict {
+                text.parse::<T>().ok()
+            } else {
+                parse::parse_leading::<T>(text)
+            };
+            if let Some(parsing) = parsing {
+                parse_time += parsing.elapsed();
+            }
+            let num = match num {
+                Some(v) if cli.nan_as_zero && v.is_nan() => {
+                    dropped.nan_coerced += 1;
+                    T::zero()
+                }
+                Some(v) if !(cli.tolerant && v.is_nan()) => v,
+                _ if cli.tolerant => {
+                    dropped.bad += 1;
+                    continue;
+                }
+                _ => bail!("Could not parse number on {}: '{line}'", location(lineno)),
+            };
+            if dropped.warmup < cli.drop_first {
+                dropped.warmup += 1;
+                continue;
+            }
+            let num = match &cli.transform {
+                Some(expr) => expr.eval(num),
+                None => num,
+            };
+            let num = if cli.abs { num.abs() } else { num };
+            let num = match shift {
+                Some(shift) => num + shift,
+                None => num,
+            };
+
+            // Each filter counts the values it drops
+            let mut num = num;
+            let keep = if cli.finite_only && num.is_infinite() {
+                dropped.infinite += 1;
+                false
+            } else if let Some((lo, hi)) = bounds.filter(|&(lo, hi)| num < lo || num > hi) {
+                dropped.out_of_range += 1;
+                num = num.max(lo).min(hi);
+                cli.clip
+            } else {
+                true
+            };
+            let keep = match distinct.as_mut() {
+                Some(distinct) if keep => {
+                    let first = distinct.insert(num);
+                    if !first {
+                        dropped.duplicates += 1;
+                    }
+                    first
+                }
+                _ => keep,
+            };
+
+            if keep {
+                if let (Some(out), Some(z)) = (outliers.as_mut(), stats.zscore(num)) {
+                    if z.abs() > threshold {
+                        writeln!(out, "{}\t{z}\t{num}", header_lines + lineno + 1)?;
+                    }
+                }
+                if let Some(robust) = robust.as_mut() {
+                    robust.push(num, &mut out, &style.na)?;
+                }
+
+                // Estimator panics are caught to report what was computed so far
+                let updated = panic::catch_unwind(AssertUnwindSafe(|| {
+                    stats.update_weighted(num, weight);
+                    if let Some(file_stats) = file_stats.as_mut() {
+                        file_stats.update_weighted(num, weight);
+                    }
+                    if let Some((_, _, segment_stats, _)) = segment.as_mut() {
+                        segment_stats.update_weighted(num, weight);
+                    }
+                    if let Some([negative, non_negative]) = sign_stats.as_mut() {
+                        if num < T::zero() {
+                            negative.update_weighted(num, weight);
+                        } else if num >= T::zero() {
+                            non_negative.update_weighted(num, weight);
+                        }
+                    }
+                }));
+                if let Err(panic) = updated {
+                    if !cli.recover_from_panic {
+                        panic::resume_unwind(panic);
+                    }
+                    if let Some(display) = display.as_mut() {
+                        display.clear()?;
+                    }
+                    out.flush()?;
+                    eprintln!(
+                        "Recovered from a panic on {}, statistics so far:\n{}",
+                        location(lineno),
+                        summarize(cli, label, &stats, Some(&dropped))
+                            .render(&style_for_display(cli))
+                    );
+                    process::exit(PANIC_EXIT_CODE);
+                }
+                if stats.unsorted {
+                    bail!(
+                        "Value on {} is smaller than the previous one with --sorted",
+                        location(lineno)
+                    );
+                }
+                if single_precision && !precision_loss {
+                    precision_loss =
+                        stats.count > 1 << 24 || (num.is_finite() && num.abs() > f32_limit);
+                }
+            }
+
+            if let Some(path) = cli
+                .progress_file
+                .as_ref()
+                .filter(|_| lines_read.is_multiple_of(cli.polling))
+            {
+                let progress =
+                    json!({"lines": lines_read, "elapsed_seconds": start.elapsed().as_secs_f64()});
+                write_atomic(path, &format!("{progress}\n"))?;
+            }
+
+            if let Some(server) = server
+                .as_ref()
+                .filter(|_| lines_read.is_multiple_of(cli.polling))
+            {
+                server.publish(
+                    summary_json(cli, &summarize(cli, label, &stats, Some(&dropped))).to_string(),
+                );
+            }
+
+            if cli.ndjson_stream && lines_read.is_multiple_of(cli.polling) {
+                let mut record = summarize(cli, label, &stats, Some(&dropped));
+                record.push("Lines", "lines", Metric::Count(lines_read));
+                writeln!(out, "{}", summary_json(cli, &record))?;
+                // Records are meant to be read as they come, whatever the buffering
+                out.flush()?;
+            }
+        }
+
+        if let Some(mut file_stats) = file_stats {
+            file_stats.finalize();
+            let name = name.unwrap_or_else(|| "stdin".to_owned());
+            let file_dropped = dropped.since(&file_start);
+            file_summaries.push(summarize(
+                cli,
+                Some(&name),
+                &file_stats,
+                Some(&file_dropped),
+            ));
         }
+        if partial {
+            break;
+        }
+    }
 
-        stats.update(num)
+    if let Some(display) = display.as_mut() {
+        display.clear()?;
     }
 
-    // Clear stderr
-    if running {
-        stderr.execute(cursor::MoveUp(running_print_height))?;
-        stderr.execute(terminal::Clear(terminal::ClearType::FromCursorDown))?;
+    if let Some(path) = &cli.progress_file {
+        let progress = json!({
+            "lines": lines_read,
+            "elapsed_seconds": start.elapsed().as_secs_f64(),
+            "done": true,
+        });
+        write_atomic(path, &format!("{progress}\n"))?;
     }
 
-    if json {
-        println!("{}", stats.to_json())
+    if cli.timing {
+        let total = start.elapsed();
+        eprintln!(
+            "Timing: {:.3}s in total, {:.3}s ({:.1}%) drawing running values, {:.3}s reading and computing",
+            total.as_secs_f64(),
+            display_time.as_secs_f64(),
+            100.0 * display_time.as_secs_f64() / total.as_secs_f64().max(f64::EPSILON),
+            (total - display_time).as_secs_f64(),
+        );
+    }
+
+    if cli.parse_stats {
+        // Lines too long have no known length
+        let text_lines = lines_read - dropped.long_lines;
+        let parsed = text_lines - dropped.bad;
+        let seconds = start.elapsed().as_secs_f64().max(f64::EPSILON);
+        let parse_seconds = parse_time.as_secs_f64().max(f64::EPSILON);
+        let report = json!({
+            "lines": lines_read,
+            "parsed": parsed,
+            "bad_skipped": dropped.bad,
+            "long_lines_skipped": dropped.long_lines,
+            "average_line_bytes": bytes_read as f64 / text_lines.max(1) as f64,
+            "seconds": seconds,
+            "parse_seconds": parse_seconds,
+            "lines_per_second": lines_read as f64 / seconds,
+            "parse_lines_per_second": text_lines as f64 / parse_seconds,
+        });
+        eprintln!("{}", serde_json::to_string_pretty(&report)?);
+    }
+    if cli.debug_quantiles {
+        eprintln!(
+            "{}",
+            serde_json::to_string_pretty(&stats.quantile_markers())?
+        );
+    }
+
+    stats.finalize();
+    if stats.exact_overflowed && !stats.exact_threshold && !cli.no_warnings {
+        eprintln!(
+            "Warning: --exact buffer reached {} bytes, quantiles are streaming estimates",
+            cli.exact_cap
+        );
+    }
+    if distinct.as_ref().is_some_and(|d| d.overflowed) && !cli.no_warnings {
+        eprintln!(
+            "Warning: more than {} distinct values, later values were not deduplicated",
+            cli.max_distinct
+        );
+    }
+    let counts_overflowed = stats
+        .value_counts
+        .as_ref()
+        .is_some_and(|(counts, _)| counts.overflowed);
+    if counts_overflowed && !cli.no_warnings {
+        eprintln!(
+            "Warning: more than {} distinct values, later values were not counted by --value-counts",
+            cli.max_distinct
+        );
+    }
+    if precision_loss && !cli.no_warnings {
+        eprintln!(
+            "Warning: more than 2^24 values or a magnitude above 2^24 in single precision, \
+            results may be imprecise, consider --use-doubles"
+        );
+    }
+
+    let mut summaries = file_summaries;
+    // Unless the input ended with a marker
+    if let Some((_, index, stats, start)) =
+        segment.filter(|(_, index, stats, _)| stats.count > 0 || *index == 1)
+    {
+        summaries.push(segment_summary(cli, stats, index, &dropped.since(&start)));
+    }
+    if let Some(sign_stats) = sign_stats {
+        for (name, mut sign_stats) in ["negative", "non-negative"].into_iter().zip(sign_stats) {
+            sign_stats.finalize();
+            // Values are dropped before their sign is known, or without one
+            summaries.push(summarize(cli, Some(name), &sign_stats, None));
+        }
+    }
+    let mut summary = summarize(cli, label, &stats, Some(&dropped));
+    if cli.ndjson_stream {
+        // Don't repeat the record already streamed on the last polling line
+        if partial || lines_read == 0 || !lines_read.is_multiple_of(cli.polling) {
+            summary.push("Lines", "lines", Metric::Count(lines_read));
+            summaries.push(summary);
+        }
     } else {
-        println!("{}", stats)
+        summaries.push(summary);
+    }
+    if cli.abort_after.is_some() {
+        for summary in summaries.iter_mut() {
+            summary.push("Partial", "partial", Metric::Bool(partial));
+        }
+    }
+    if let (Some(server), Some(summary)) = (&server, summaries.last()) {
+        server.publish(summary_json(cli, summary).to_string());
+    }
+
+    if let Some(robust) = &robust {
+        robust.finish(&mut out, &style.na)?;
+        out.flush()?;
+        print_summaries(cli, &mut io::stderr(), &summaries)?;
+    } else if let Some(points) = cli.cdf_points {
+        write_cdf(&mut out, &stats, points.get(), &style)?;
+    } else {
+        print_summaries(cli, &mut out, &summaries)?;
+    }
+    out.flush()?;
+
+    if let (Some(dir), Some(summary)) = (&cli.split_dir, summaries.last()) {
+        write_split(dir, summary, &style)?;
+    }
+
+    let current = summaries.last().map(|summary| summary.to_json());
+    if let (Some(baseline), Some(current)) = (&baseline, &current) {
+        let drifts = baseline::drifts(baseline, current, cli.tolerance);
+        for drift in drifts.iter() {
+            eprintln!(
+                "{}: {} against {} in the baseline, deviation {:.4}",
+                drift.key, drift.current, drift.baseline, drift.deviation
+            );
+        }
+        if !drifts.is_empty() {
+            bail!(
+                "{} metrics deviate from the baseline by more than {}",
+                drifts.len(),
+                cli.tolerance
+            );
+        }
+    }
+
+    if let Some(max) = cli.max_bad_fraction {
+        let fraction = dropped.bad as f64 / lines_read.max(1) as f64;
+        if fraction > max {
+            bail!(
+                "{} of {lines_read} lines were bad values ({fraction:.4}), more than the allowed {max}",
+                dropped.bad
+            );
+        }
     }
 
     Ok(())
@@ -202,10 +2663,124 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
 
     if cli.use_doubles {
-        compute_stats::<f64>(cli.json, !cli.hide_running, cli.polling, cli.skip_header)?;
+        compute_stats::<f64>(&cli)?;
     } else {
-        compute_stats::<f32>(cli.json, !cli.hide_running, cli.polling, cli.skip_header)?;
+        compute_stats::<f32>(&cli)?;
     };
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mean_and_sum_stay_accurate_on_large_offsets() {
+        let mut stats: Stats<f64> = new_stats(&Cli::parse_from(["stats"]));
+        let (val, n) = (1e9 + 0.1, 1_000_000);
+        (0..n).for_each(|_| stats.update(val));
+
+        assert!((stats.mean() - val).abs() < 1e-6, "{}", stats.mean());
+        assert!(
+            (stats.sum.get() - val * n as f64).abs() < 1.0,
+            "{}",
+            stats.sum.get()
+        );
+    }
+
+    #[test]
+    fn weighted_update_matches_repeated_updates() {
+        let cli = Cli::parse_from(["stats"]);
+        let (mut weighted, mut repeated): (Stats<f64>, Stats<f64>) =
+            (new_stats(&cli), new_stats(&cli));
+        for (val, weight) in [(1.0, 3), (2.0, 1), (5.0, 100_000), (-3.0, 2_000)] {
+            weighted.update_weighted(val, weight);
+            (0..weight).for_each(|_| repeated.update(val));
+        }
+
+        assert_eq!(weighted.count, repeated.count);
+        assert!((weighted.mean() - repeated.mean()).abs() < 1e-9);
+        assert!((weighted.variance.get::<f64>() - repeated.variance.get::<f64>()).abs() < 1e-6);
+        let (weighted, repeated) = (weighted.mad_mean(), repeated.mad_mean());
+        assert!(
+            (weighted - repeated).abs() < 1e-9,
+            "{weighted} != {repeated}"
+        );
+    }
+
+    #[test]
+    fn dispersion_index_is_one_for_poisson_counts() {
+        let mut stats: Stats<f64> = new_stats(&Cli::parse_from(["stats"]));
+        // Each count k of a Poisson(4) sample of 100000, as often as expected
+        let (lambda, mut pmf) = (4.0f64, (-4.0f64).exp());
+        for k in 0..30 {
+            stats.update_weighted(k as f64, (100_000.0 * pmf).round() as u64);
+            pmf *= lambda / (k + 1) as f64;
+        }
+
+        let index = stats.summary().to_json()["dispersion_index"]
+            .as_f64()
+            .unwrap();
+        assert!((index - 1.0).abs() < 0.01, "{index}");
+    }
+
+    #[test]
+    fn dispersion_index_is_na_for_a_zero_mean() {
+        let mut stats: Stats<f64> = new_stats(&Cli::parse_from(["stats"]));
+        [-1.0, 1.0].into_iter().for_each(|v| stats.update(v));
+        assert!(stats.summary().to_json()["dispersion_index"].is_null());
+    }
+
+    #[test]
+    fn stable_schema_keys_dont_depend_on_the_options() {
+        let keys = |args: &[&str], values: &[f64]| {
+            let cli = Cli::parse_from(["stats", "--stable-schema"].iter().chain(args));
+            let mut stats: Stats<f64> = new_stats(&cli);
+            values.iter().for_each(|&v| stats.update(v));
+            stats.finalize();
+            let summary = summarize(&cli, None, &stats, Some(&Dropped::default()));
+            let json = summary_json(&cli, &summary);
+            json.as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        let values: Vec<f64> = (1..=10).map(f64::from).collect();
+        let expected = keys(&[], &values);
+        let options: [&[&str]; 9] = [
+            &["--exact"],
+            &["--range-only"],
+            &["--approx-median"],
+            &["--sorted"],
+            &["--sample-quantiles", "5"],
+            &["--checksum"],
+            &["--exact", "--detect-modes"],
+            &["--k-extremes", "2", "--window", "3"],
+            &["--bignum-strings", "--exact", "--percentile-error"],
+        ];
+        for args in options {
+            assert_eq!(keys(args, &values), expected, "{args:?}");
+        }
+        assert_eq!(keys(&[], &[]), expected, "no values");
+    }
+
+    #[test]
+    fn harmonic_span_matches_direct_sums() {
+        for (from, to) in [
+            (0, 1),
+            (0, 5000),
+            (10, 3000),
+            (100, 100_000),
+            (1 << 20, 1 << 24),
+        ] {
+            let direct: f64 = (from + 1..=to).map(|i| 1.0 / i as f64).sum();
+            assert!(
+                (harmonic_span(from, to) - direct).abs() < 1e-10,
+                "{from}..{to}"
+            );
+        }
+    }
+}
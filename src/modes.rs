@@ -0,0 +1,88 @@
+/// Modes found in a histogram of the data
+pub struct Modes {
+    /// Bin centers of the modes, in increasing order
+    pub locations: Vec<f64>,
+    pub bins: usize,
+}
+
+/// Number of bins by the Freedman-Diaconis rule, kept within 10 and 200
+fn default_bins(sorted: &[f64]) -> usize {
+    let n = sorted.len() as f64;
+    let at = |q: f64| sorted[((n - 1.0) * q).round() as usize];
+    let iqr = at(0.75) - at(0.25);
+    let range = sorted[sorted.len() - 1] - sorted[0];
+    if iqr <= 0.0 {
+        return 10;
+    }
+    let width = 2.0 * iqr / n.cbrt();
+    ((range / width).ceil() as usize).clamp(10, 200)
+}
+
+/// Find the local maxima of a histogram of sorted finite values whose
+/// prominence, the height above the highest of the lowest points separating
+/// them from a higher peak, is at least `prominence` times the tallest bin.
+/// The histogram is smoothed with a 3 bin moving average first so that
+/// sampling noise doesn't show as modes.
+pub fn detect_modes(sorted: &[f64], bins: Option<usize>, prominence: f64) -> Option<Modes> {
+    let (&lo, &hi) = (sorted.first()?, sorted.last()?);
+    if !(lo.is_finite() && hi.is_finite()) {
+        return None;
+    }
+    if lo == hi {
+        return Some(Modes {
+            locations: vec![lo],
+            bins: 1,
+        });
+    }
+
+    let bins = bins.unwrap_or_else(|| default_bins(sorted));
+    let width = (hi - lo) / bins as f64;
+    let mut counts = vec![0.0; bins];
+    for v in sorted {
+        let bin = (((v - lo) / width) as usize).min(bins - 1);
+        counts[bin] += 1.0;
+    }
+
+    let smoothed: Vec<f64> = (0..bins)
+        .map(|i| {
+            let window = &counts[i.saturating_sub(1)..(i + 2).min(bins)];
+            window.iter().sum::<f64>() / window.len() as f64
+        })
+        .collect();
+
+    let tallest = smoothed.iter().cloned().fold(0.0, f64::max);
+    let threshold = prominence * tallest;
+    let mut locations = vec![];
+    for i in 0..bins {
+        let h = smoothed[i];
+        let rises = i == 0 || smoothed[i - 1] < h;
+        let falls = i == bins - 1 || smoothed[i + 1] <= h;
+        if !(rises && falls) {
+            continue;
+        }
+
+        // Lowest points on each side before reaching a higher bin
+        let left = smoothed[..i]
+            .iter()
+            .rev()
+            .take_while(|&&x| x <= h)
+            .fold(h, |m, &x| m.min(x));
+        let right = smoothed[i + 1..]
+            .iter()
+            .take_while(|&&x| x <= h)
+            .fold(h, |m, &x| m.min(x));
+        let reaches_higher_left = smoothed[..i].iter().any(|&x| x > h);
+        let reaches_higher_right = smoothed[i + 1..].iter().any(|&x| x > h);
+        let base = match (reaches_higher_left, reaches_higher_right) {
+            (true, true) => left.max(right),
+            (true, false) => left,
+            (false, true) => right,
+            (false, false) => left.min(right),
+        };
+        if h - base >= threshold || h == tallest {
+            locations.push(lo + (i as f64 + 0.5) * width);
+        }
+    }
+
+    Some(Modes { locations, bins })
+}
@@ -0,0 +1,60 @@
+use std::str::FromStr;
+
+/// Length of the longest prefix of `s` that looks like a floating point
+/// number, in the spirit of C's `atof`
+pub fn numeric_prefix_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    if matches!(bytes.first(), Some(b'+' | b'-')) {
+        i += 1;
+    }
+
+    let rest = s[i..].to_ascii_lowercase();
+    for word in ["infinity", "inf", "nan"] {
+        if rest.starts_with(word) {
+            return i + word.len();
+        }
+    }
+
+    let digits = |from: usize| {
+        bytes[from..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+    let int = digits(i);
+    i += int;
+    let mut frac = 0;
+    if bytes.get(i) == Some(&b'.') {
+        frac = digits(i + 1);
+        if int + frac > 0 {
+            i += 1 + frac;
+        }
+    }
+    if int + frac == 0 {
+        return 0;
+    }
+
+    // Only take the exponent if it has digits, so '2e' parses as 2
+    if matches!(bytes.get(i), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(bytes.get(i + 1), Some(b'+' | b'-')));
+        let exp = digits(i + 1 + sign);
+        if exp > 0 {
+            i += 1 + sign + exp;
+        }
+    }
+    i
+}
+
+/// Parse the number at the start of `s`, ignoring anything after it,
+/// e.g. '3.14ms' is read as 3.14
+pub fn parse_leading<T: FromStr>(s: &str) -> Option<T> {
+    let s = s.trim_start();
+    s[..numeric_prefix_len(s)].parse().ok()
+}
+
+/// Split a 'value count' line into the value text and its integer count
+pub fn split_count(line: &str) -> Option<(&str, u64)> {
+    let (value, count) = line.trim().rsplit_once(char::is_whitespace)?;
+    Some((value.trim_end(), count.parse().ok()?))
+}
@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+/// Shortest time between two redraws every `n` lines, so that a small
+/// polling interval can't make redrawing dominate the run time
+const MIN_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Decides on which lines the running display is redrawn
+pub enum Redraw {
+    /// Every `n` lines, at most once per `MIN_INTERVAL`
+    Every { n: u64, last: Option<Instant> },
+    /// Every `interval` lines, tuned to the measured line rate so that
+    /// redraws happen about once per `target`
+    Adaptive {
+        target: Duration,
+        interval: u64,
+        since: u64,
+        last: Instant,
+    },
+}
+
+impl Redraw {
+    pub fn every(n: u64) -> Self {
+        Redraw::Every { n, last: None }
+    }
+
+    pub fn adaptive(target: Duration) -> Self {
+        Redraw::Adaptive {
+            target,
+            interval: 1,
+            since: 0,
+            last: Instant::now(),
+        }
+    }
+
+    /// Whether to redraw before reading line number `lines_read`
+    pub fn due(&mut self, lines_read: u64) -> bool {
+        match self {
+            Redraw::Every { n, last } => {
+                if !lines_read.is_multiple_of(*n)
+                    || last.is_some_and(|l| l.elapsed() < MIN_INTERVAL)
+                {
+                    return false;
+                }
+                *last = Some(Instant::now());
+                true
+            }
+            Redraw::Adaptive {
+                target,
+                interval,
+                since,
+                last,
+            } => {
+                *since += 1;
+                if *since < *interval {
+                    return false;
+                }
+
+                let elapsed = last.elapsed().as_secs_f64();
+                let rate = *since as f64 / elapsed.max(f64::EPSILON);
+                *interval = ((rate * target.as_secs_f64()) as u64).max(1);
+                *since = 0;
+                *last = Instant::now();
+                true
+            }
+        }
+    }
+}
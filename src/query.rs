@@ -0,0 +1,63 @@
+use std::str::FromStr;
+
+use num_traits::{Float, FromPrimitive};
+
+use crate::exact;
+
+/// Question asked of the sorted exact values
+#[derive(Clone, Copy)]
+pub enum Query {
+    /// 'q P', the quantile at level P in [0, 1]
+    Quantile(f64),
+    /// 'rank X', the fraction of values lower than or equal to X
+    Rank(f64),
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, arg) = s
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("'{s}' is not of the form 'q P' or 'rank X'"))?;
+        let arg = arg.trim();
+        let value: f64 = arg
+            .parse()
+            .map_err(|_| format!("'{arg}' is not a number"))?;
+        match kind {
+            "q" if (0.0..=1.0).contains(&value) => Ok(Self::Quantile(value)),
+            "q" => Err(format!("{value} is not between 0 and 1")),
+            "rank" => Ok(Self::Rank(value)),
+            _ => Err(format!("Unknown query '{kind}', expected 'q' or 'rank'")),
+        }
+    }
+}
+
+impl Query {
+    pub fn label(&self) -> String {
+        match self {
+            Self::Quantile(p) => format!("q {p}"),
+            Self::Rank(x) => format!("rank {x}"),
+        }
+    }
+
+    pub fn key(&self) -> String {
+        match self {
+            Self::Quantile(p) => format!("q_{p}"),
+            Self::Rank(x) => format!("rank_{x}"),
+        }
+    }
+
+    pub fn answer<T: Float + FromPrimitive>(&self, sorted: &[T]) -> Option<T> {
+        match *self {
+            Self::Quantile(p) => exact::quantile(sorted, T::from_f64(p)?),
+            Self::Rank(_) if sorted.is_empty() => None,
+            Self::Rank(x) => {
+                let x = T::from_f64(x)?;
+                let below = sorted.partition_point(|&v| v <= x);
+                T::from_f64(below as f64 / sorted.len() as f64)
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::io::{self, BufRead};
+
+/// UTF-8 byte order mark, written at the start of files by some Windows tools
+pub const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// A line read from an input, without its line ending
+pub enum Line {
+    Text(String),
+    /// The line was longer than the limit, its content was discarded
+    TooLong,
+}
+
+/// Line iterator that never buffers more than `max_len` bytes of a line,
+/// so that a single huge line can't exhaust memory. Lines come with the
+/// byte offset of their start in the stream. CRLF line endings and a leading
+/// byte order mark are stripped
+pub struct LineReader {
+    reader: Box<dyn BufRead + Send>,
+    max_len: Option<usize>,
+    offset: u64,
+}
+
+impl LineReader {
+    pub fn new(reader: Box<dyn BufRead + Send>, max_len: Option<usize>) -> Self {
+        Self {
+            reader,
+            max_len,
+            offset: 0,
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<(u64, Line)>> {
+        let start = self.offset;
+        let mut buf = vec![];
+        let mut too_long = false;
+        let mut read_any = false;
+
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if available.is_empty() {
+                break;
+            }
+            read_any = true;
+
+            let newline = available.iter().position(|&b| b == b'\n');
+            let chunk = &available[..newline.unwrap_or(available.len())];
+            if !too_long {
+                if self
+                    .max_len
+                    .is_some_and(|max| buf.len() + chunk.len() > max)
+                {
+                    too_long = true;
+                    buf = vec![];
+                } else {
+                    buf.extend_from_slice(chunk);
+                }
+            }
+
+            let used = newline.map_or(available.len(), |i| i + 1);
+            self.reader.consume(used);
+            self.offset += used as u64;
+            if newline.is_some() {
+                break;
+            }
+        }
+
+        if !read_any {
+            return Ok(None);
+        }
+        if too_long {
+            return Ok(Some((start, Line::TooLong)));
+        }
+
+        if buf.last() == Some(&b'\r') {
+            buf.pop();
+        }
+        if start == 0 && buf.starts_with(BOM) {
+            buf.drain(..BOM.len());
+        }
+        String::from_utf8(buf)
+            .map(|s| Some((start, Line::Text(s))))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{e} at byte {start}")))
+    }
+}
+
+impl Iterator for LineReader {
+    type Item = io::Result<(u64, Line)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_line().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(input: &'static str) -> Vec<f64> {
+        LineReader::new(Box::new(input.as_bytes()), None)
+            .map(|line| match line.unwrap() {
+                (_, Line::Text(text)) => text.parse().unwrap(),
+                (_, Line::TooLong) => panic!("line too long"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bom_and_crlf_line_endings_are_stripped() {
+        assert_eq!(values("\u{feff}1\r\n2\r\n"), [1.0, 2.0]);
+        // Without a final line ending
+        assert_eq!(values("\u{feff}1\r\n2"), [1.0, 2.0]);
+    }
+
+    #[test]
+    fn only_a_leading_bom_is_stripped() {
+        let mut lines = LineReader::new(Box::new("1\n\u{feff}2\n".as_bytes()), None);
+        lines.next();
+        assert!(matches!(lines.next(), Some(Ok((2, Line::Text(text)))) if text == "\u{feff}2"));
+    }
+}
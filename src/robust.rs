@@ -0,0 +1,115 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::ops::{AddAssign, SubAssign};
+
+use num_traits::{Float, FromPrimitive};
+use watermill::quantile::Quantile;
+use watermill::stats::Univariate;
+
+use crate::exact;
+
+/// Robust standardization of values as (x - median) / MAD, with MAD the
+/// median absolute deviation from the median. Values whose scale is unknown,
+/// or zero, are written as the NA token
+pub enum RobustScaler<T>
+where
+    T: Float + FromPrimitive + AddAssign + SubAssign + Display,
+{
+    /// Each value is scaled on arrival against the running estimates of the
+    /// values before it, the MAD being the running median of the deviations
+    /// from the running median. NaN values and deviations are left out
+    Streaming {
+        median: Quantile<T>,
+        mad: Quantile<T>,
+        count: u64,
+        deviations: u64,
+    },
+    /// Every value is kept and scaled at EOF with the exact median and MAD
+    Exact(Vec<T>),
+}
+
+impl<T> RobustScaler<T>
+where
+    T: Float + FromPrimitive + AddAssign + SubAssign + Display,
+{
+    pub fn streaming() -> Self {
+        let half = T::from_f32(0.5).unwrap();
+        Self::Streaming {
+            median: Quantile::new(half).unwrap(),
+            mad: Quantile::new(half).unwrap(),
+            count: 0,
+            deviations: 0,
+        }
+    }
+
+    pub fn exact() -> Self {
+        Self::Exact(vec![])
+    }
+
+    /// Write the scaled value in streaming mode, or keep it for `finish`
+    pub fn push(&mut self, val: T, out: &mut dyn Write, na: &str) -> io::Result<()> {
+        match self {
+            Self::Streaming {
+                median,
+                mad,
+                count,
+                deviations,
+            } => {
+                // The estimators have no value before their first update
+                let scaled = (*deviations >= 1).then(|| (val - median.get()) / mad.get());
+                write_scaled(out, scaled, na)?;
+                if val.is_nan() {
+                    return Ok(());
+                }
+                let deviation = (*count >= 1).then(|| (val - median.get()).abs());
+                if let Some(deviation) = deviation.filter(|d| !d.is_nan()) {
+                    mad.update(deviation);
+                    *deviations += 1;
+                }
+                median.update(val);
+                *count += 1;
+            }
+            Self::Exact(values) => values.push(val),
+        }
+        Ok(())
+    }
+
+    /// Write the scaled values kept in exact mode, in their input order
+    pub fn finish(&self, out: &mut dyn Write, na: &str) -> io::Result<()> {
+        let Self::Exact(values) = self else {
+            return Ok(());
+        };
+
+        let half = T::from_f32(0.5).unwrap();
+        // NaN values are left out, so are the NaN deviations of infinite values
+        // from an infinite median, the MAD being undefined if none is left
+        let sorted_finite = |v: Vec<T>| {
+            let mut v: Vec<T> = v.into_iter().filter(|v| !v.is_nan()).collect();
+            v.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+            v
+        };
+        let sorted = sorted_finite(values.clone());
+        let Some(median) = exact::quantile(&sorted, half) else {
+            return Ok(());
+        };
+        let deviations = sorted_finite(sorted.iter().map(|v| (*v - median).abs()).collect());
+        let mad = exact::quantile(&deviations, half);
+
+        for &val in values {
+            write_scaled(out, mad.map(|mad| (val - median) / mad), na)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_scaled<T: Float + Display>(
+    out: &mut dyn Write,
+    scaled: Option<T>,
+    na: &str,
+) -> io::Result<()> {
+    match scaled.filter(|v| v.is_finite()) {
+        Some(v) => writeln!(out, "{v}"),
+        None => writeln!(out, "{na}"),
+    }
+}
@@ -0,0 +1,153 @@
+use num_traits::{Float, FromPrimitive};
+
+use crate::exact;
+
+/// SplitMix64, a small and fast generator that is plenty for sampling
+pub struct SplitMix64(pub u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform integer in `0..n`
+    pub fn below(&mut self, n: u64) -> u64 {
+        ((self.next() as u128 * n as u128) >> 64) as u64
+    }
+
+    /// Uniform float in `(0, 1]`
+    fn unit(&mut self) -> f64 {
+        ((self.next() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Uniform sample of `size` values of the stream (Li's algorithm L), sorted
+/// at EOF to compute quantiles in bounded memory. The number of values to
+/// skip before the next one enters the sample is drawn directly, so runs of
+/// repeated values cost no more than the values that enter it
+pub struct Reservoir<T> {
+    values: Vec<T>,
+    size: usize,
+    seen: u64,
+    rng: SplitMix64,
+    sorted: bool,
+    // Largest key of the sample and index of the next value to enter it
+    w: f64,
+    next: u64,
+}
+
+impl<T> Reservoir<T>
+where
+    T: Float + FromPrimitive,
+{
+    pub fn new(size: usize, seed: u64) -> Self {
+        Self {
+            // Grown as values come, `size` may be far larger than the input
+            values: Vec::with_capacity(size.min(1024)),
+            size,
+            seen: 0,
+            rng: SplitMix64(seed),
+            sorted: false,
+            w: 1.0,
+            next: 0,
+        }
+    }
+
+    /// Draw the key and index of the next value to enter after value `from`
+    fn skip(&mut self, from: u64) {
+        self.w *= (self.rng.unit().ln() / self.size as f64).exp();
+        let skipped = (self.rng.unit().ln() / (1.0 - self.w).ln()).floor();
+        self.next = from.saturating_add(skipped as u64).saturating_add(1);
+    }
+
+    /// Add `n` copies of a value
+    pub fn update_repeated(&mut self, val: T, n: u64) {
+        self.sorted = false;
+        let end = self.seen + n;
+        while self.seen < end && self.values.len() < self.size {
+            self.values.push(val);
+            self.seen += 1;
+            if self.values.len() == self.size {
+                self.skip(self.seen);
+            }
+        }
+        while self.values.len() == self.size && self.next <= end {
+            let i = self.rng.below(self.size as u64) as usize;
+            self.values[i] = val;
+            self.skip(self.next);
+        }
+        self.seen = end;
+    }
+
+    /// Number of values in the sample, fewer than its size until that many are seen
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn sort(&mut self) {
+        self.values
+            .sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        self.sorted = true;
+    }
+
+    /// Quantile of the sample, only available once `sort` has been called
+    pub fn quantile(&self, q: T) -> Option<T> {
+        if !self.sorted {
+            return None;
+        }
+        exact::quantile(&self.values, q)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use watermill::quantile::Quantile;
+    use watermill::stats::Univariate;
+
+    use super::*;
+
+    #[test]
+    fn sample_shorter_than_its_size_reports_its_length() {
+        let mut reservoir = Reservoir::new(100, 1);
+        (0..10).for_each(|i| reservoir.update_repeated(i as f64, 1));
+        assert_eq!(reservoir.len(), 10);
+    }
+
+    #[test]
+    fn sample_beats_p2_on_bimodal_data() {
+        // 120000 values around 0 followed by 80000 around 100, as when a stream
+        // changes regime, which the P² markers can't follow
+        let mut rng = SplitMix64(7);
+        let values: Vec<f64> = (0..200_000)
+            .map(|i| if i < 120_000 { 0.0 } else { 100.0 } + rng.unit())
+            .collect();
+
+        let mut reservoir = Reservoir::new(10_000, 42);
+        let qs = [0.1, 0.25, 0.5, 0.75, 0.9];
+        let mut p2: Vec<Quantile<f64>> = qs.iter().map(|&q| Quantile::new(q).unwrap()).collect();
+        for &v in &values {
+            reservoir.update_repeated(v, 1);
+            p2.iter_mut().for_each(|p| p.update(v));
+        }
+        reservoir.sort();
+        let mut sorted = values;
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let (mut sample_error, mut p2_error) = (0.0, 0.0);
+        for (&q, p2) in qs.iter().zip(&p2) {
+            let exact = exact::quantile(&sorted, q).unwrap();
+            let error = (reservoir.quantile(q).unwrap() - exact).abs();
+            assert!(error < 0.05, "q{q}: sample off by {error}");
+            sample_error += error;
+            p2_error += (p2.get() - exact).abs();
+        }
+        assert!(
+            sample_error * 100.0 < p2_error,
+            "{sample_error} vs {p2_error}"
+        );
+    }
+}
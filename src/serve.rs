@@ -0,0 +1,66 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Minimal HTTP server answering `GET /stats` with the latest published
+/// json summary. Connections are handled one at a time on a background
+/// thread, which only ever reads the summary under the lock
+pub struct Server {
+    latest: Arc<Mutex<String>>,
+}
+
+impl Server {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let latest = Arc::new(Mutex::new("null".to_owned()));
+
+        let shared = Arc::clone(&latest);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A failing client must not stop the server
+                let _ = respond(stream, &shared);
+            }
+        });
+
+        Ok(Self { latest })
+    }
+
+    /// Replace the summary served to new requests
+    pub fn publish(&self, json: String) {
+        *self.latest.lock().unwrap() = json;
+    }
+}
+
+fn respond(stream: TcpStream, latest: &Mutex<String>) -> io::Result<()> {
+    // Don't let a silent client hold up the others
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Skip the headers, up to the empty line ending them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+        header.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/stats")) => ("200 OK", latest.lock().unwrap().clone()),
+        (Some("GET"), _) => ("404 Not Found", r#"{"error":"not found"}"#.to_owned()),
+        _ => (
+            "405 Method Not Allowed",
+            r#"{"error":"method not allowed"}"#.to_owned(),
+        ),
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+        Connection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
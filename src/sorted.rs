@@ -0,0 +1,67 @@
+use std::collections::VecDeque;
+
+use num_traits::{Float, FromPrimitive};
+
+/// Exact quantiles of values arriving in ascending order. Values ranked
+/// below the lowest quantile of interest can never be needed again once
+/// seen, so only the ones from that rank on are kept. This is a constant
+/// fraction of the values, memory still grows linearly with the input.
+pub struct SortedTail<T> {
+    values: VecDeque<T>,
+    // Number of values discarded from the front
+    dropped: usize,
+    lowest: f64,
+}
+
+/// Lower and upper ranks of quantile `q` of `n` values, and the weight of the upper one
+fn ranks(q: f64, n: usize) -> (usize, usize, f64) {
+    let rank = q * (n - 1) as f64;
+    (rank.floor() as usize, rank.ceil() as usize, rank.fract())
+}
+
+impl<T> SortedTail<T>
+where
+    T: Float + FromPrimitive,
+{
+    /// Quantiles below `lowest` are unknown
+    pub fn new(lowest: f64) -> Self {
+        Self {
+            values: VecDeque::new(),
+            dropped: 0,
+            lowest,
+        }
+    }
+
+    /// Store a value, returns false if it is smaller than the previous one
+    pub fn push(&mut self, val: T) -> bool {
+        if self.values.back().is_some_and(|&last| val < last) {
+            return false;
+        }
+        self.values.push_back(val);
+
+        let (needed, _, _) = ranks(self.lowest, self.dropped + self.values.len());
+        while self.dropped < needed {
+            self.values.pop_front();
+            self.dropped += 1;
+        }
+        true
+    }
+
+    pub fn quantile(&self, q: T) -> Option<T> {
+        let n = self.dropped + self.values.len();
+        if n == 0 {
+            return None;
+        }
+        let (lower, upper, frac) = ranks(q.to_f64()?, n);
+        let lower_value = *self.values.get(lower.checked_sub(self.dropped)?)?;
+        if lower == upper || frac == 0.0 {
+            return Some(lower_value);
+        }
+        let upper_value = *self.values.get(upper - self.dropped)?;
+        if lower_value == upper_value {
+            return Some(lower_value);
+        }
+
+        Some(lower_value + (upper_value - lower_value) * T::from_f64(frac)?)
+    }
+}
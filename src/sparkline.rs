@@ -0,0 +1,46 @@
+use std::collections::VecDeque;
+
+const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Last `size` samples of a running metric, drawn as a line of block
+/// characters scaled between their minimum and maximum
+pub struct Sparkline {
+    values: VecDeque<f64>,
+    size: usize,
+}
+
+impl Sparkline {
+    pub fn new(size: usize) -> Self {
+        Self {
+            values: VecDeque::with_capacity(size),
+            size,
+        }
+    }
+
+    /// Record a sample, non-finite ones are drawn as gaps
+    pub fn update(&mut self, val: f64) {
+        if self.values.len() == self.size {
+            self.values.pop_front();
+        }
+        self.values.push_back(val);
+    }
+
+    pub fn render(&self) -> String {
+        let finite = self.values.iter().filter(|v| v.is_finite());
+        let min = finite.clone().fold(f64::INFINITY, |a, &b| a.min(b));
+        let max = finite.fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+
+        self.values
+            .iter()
+            .map(|&v| match v {
+                v if !v.is_finite() => ' ',
+                // Constant samples sit in the middle
+                _ if max == min => BLOCKS[BLOCKS.len() / 2],
+                v => {
+                    let level = (v - min) / (max - min) * (BLOCKS.len() - 1) as f64;
+                    BLOCKS[level.round() as usize]
+                }
+            })
+            .collect()
+    }
+}
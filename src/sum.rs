@@ -0,0 +1,54 @@
+use num_traits::Float;
+
+/// Running sum with Neumaier's compensated summation, in double precision.
+///
+/// The low order bits lost by each addition are accumulated separately and
+/// added back when the sum is read, so the error stays of the order of one
+/// rounding however many values are summed, instead of growing with the count.
+#[derive(Default)]
+pub struct NeumaierSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl NeumaierSum {
+    pub fn update<T: Float>(&mut self, val: T) {
+        let val = val.to_f64().unwrap();
+        let sum = self.sum + val;
+        if self.sum.abs() >= val.abs() {
+            self.compensation += (self.sum - sum) + val;
+        } else {
+            self.compensation += (val - sum) + self.sum;
+        }
+        self.sum = sum;
+    }
+
+    pub fn get(&self) -> f64 {
+        self.sum + self.compensation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sum(values: &[f64]) -> f64 {
+        let mut sum = NeumaierSum::default();
+        values.iter().for_each(|&v| sum.update(v));
+        sum.get()
+    }
+
+    #[test]
+    fn small_terms_survive_catastrophic_cancellation() {
+        // Naive summation gives 0, the 1 being absorbed by 1e16
+        assert_eq!(sum(&[1e16, 1.0, -1e16]), 1.0);
+        assert_eq!(sum(&[1.0, 1e100, 1.0, -1e100]), 2.0);
+    }
+
+    #[test]
+    fn many_small_terms_stay_accurate() {
+        // Naive summation is off by about 1.6e-4
+        let total = sum(&vec![0.1; 10_000_000]);
+        assert!((total - 1e6).abs() < 1e-8, "{total}");
+    }
+}
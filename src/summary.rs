@@ -0,0 +1,462 @@
+use std::fmt::{Display, LowerExp};
+
+use clap::ValueEnum;
+use crossterm::style::Stylize;
+use num_traits::Float;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// How floating point values are written in text output
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum Notation {
+    /// Decimal, switching to scientific for very large or very small magnitudes
+    Auto,
+    /// Always decimal, never using an exponent
+    #[default]
+    Decimal,
+    /// Always scientific, e.g. 1.5e3
+    Scientific,
+}
+
+/// When to color text output
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal, unless NO_COLOR is set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to color output written to a stream, `terminal` telling if it is a tty
+    pub fn enabled(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => terminal && std::env::var_os("NO_COLOR").is_none(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Rendering options for text output
+#[derive(Clone)]
+pub struct Style {
+    pub notation: Notation,
+    pub color: bool,
+    /// Fixed number of decimals of tail percentiles, which are then right aligned
+    pub percentile_digits: Option<usize>,
+    /// Columns that rendered lines must fit in, longer values are abbreviated
+    pub width: Option<usize>,
+    /// Written for undefined metrics
+    pub na: String,
+}
+
+impl Style {
+    pub fn float<T: Float + Display + LowerExp>(&self, v: T) -> String {
+        let magnitude = v.abs().to_f64().unwrap_or(0.0);
+        let extreme = v.is_finite() && v != T::zero() && !(1e-4..1e16).contains(&magnitude);
+        match self.notation {
+            Notation::Auto if extreme => format!("{:e}", v),
+            Notation::Auto | Notation::Decimal => format!("{}", v),
+            Notation::Scientific => format!("{:e}", v),
+        }
+    }
+}
+
+/// Value of a single reported metric
+pub enum Metric<T> {
+    Float(T),
+    Count(u64),
+    Text(String),
+    Bool(bool),
+    List(Vec<T>),
+    /// Values with their count and proportion
+    Frequencies(Vec<(T, usize, f64)>),
+    /// Points of an empirical CDF, values with the fraction lower than or equal to them
+    Cdf(Vec<(T, T)>),
+    NA,
+}
+
+impl<T: Float + Display + LowerExp> Metric<T> {
+    /// Value of a single number metric
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Metric::Float(v) => v.to_f64(),
+            Metric::Count(c) => Some(*c as f64),
+            _ => None,
+        }
+    }
+
+    fn render(&self, style: &Style) -> String {
+        match self {
+            Metric::Float(v) => style.float(*v),
+            Metric::Count(c) => c.to_string(),
+            Metric::Text(t) => t.clone(),
+            Metric::Bool(b) => b.to_string(),
+            Metric::List(values) => {
+                let values: Vec<String> = values.iter().map(|v| style.float(*v)).collect();
+                values.join(", ")
+            }
+            Metric::Frequencies(values) => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|(v, count, proportion)| {
+                        format!("{}: {count} ({:.2}%)", style.float(*v), proportion * 100.0)
+                    })
+                    .collect();
+                values.join(", ")
+            }
+            Metric::Cdf(points) => {
+                let points: Vec<String> = points
+                    .iter()
+                    .map(|(x, cdf)| format!("{}: {}", style.float(*x), style.float(*cdf)))
+                    .collect();
+                points.join(", ")
+            }
+            Metric::NA => style.na.clone(),
+        }
+    }
+}
+
+impl<T: Serialize> Metric<T> {
+    fn to_json(&self) -> Value {
+        match self {
+            Metric::Float(v) => serde_json::to_value(v).unwrap_or(Value::Null),
+            Metric::Count(c) => Value::from(*c),
+            Metric::Text(t) => Value::from(t.as_str()),
+            Metric::Bool(b) => Value::from(*b),
+            Metric::List(values) => serde_json::to_value(values).unwrap_or(Value::Null),
+            Metric::Frequencies(values) => values
+                .iter()
+                .map(|(v, count, proportion)| {
+                    json!({"value": v, "count": count, "proportion": proportion})
+                })
+                .collect(),
+            Metric::Cdf(points) => points
+                .iter()
+                .map(|(x, cdf)| json!({"x": x, "cdf": cdf}))
+                .collect(),
+            Metric::NA => Value::Null,
+        }
+    }
+}
+
+impl<T> Metric<T> {
+    /// JSON Schema of the value, floats are nullable since NaN is written as null
+    fn json_schema(&self) -> Value {
+        let float = json!({"type": ["number", "null"]});
+        match self {
+            Metric::Float(_) => float,
+            Metric::NA => json!({"type": "null"}),
+            Metric::Count(_) => json!({"type": "integer", "minimum": 0}),
+            Metric::Text(_) => json!({"type": "string"}),
+            Metric::Bool(_) => json!({"type": "boolean"}),
+            Metric::List(_) => json!({"type": "array", "items": float}),
+            Metric::Frequencies(_) => json!({
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "value": float,
+                        "count": {"type": "integer", "minimum": 0},
+                        "proportion": {"type": "number"},
+                    },
+                    "required": ["value", "count", "proportion"],
+                    "additionalProperties": false,
+                },
+            }),
+            Metric::Cdf(_) => json!({
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {"x": float, "cdf": float},
+                    "required": ["x", "cdf"],
+                    "additionalProperties": false,
+                },
+            }),
+        }
+    }
+}
+
+struct Row<T> {
+    label: String,
+    key: String,
+    value: Metric<T>,
+}
+
+/// Ordered list of labelled metrics, shared by the text and JSON outputs
+pub struct Summary<T> {
+    rows: Vec<Row<T>>,
+}
+
+impl<T> Default for Summary<T> {
+    fn default() -> Self {
+        Self { rows: vec![] }
+    }
+}
+
+impl<T> Summary<T>
+where
+    T: Display + Serialize,
+{
+    /// Add a metric, `label` is used in text output and `key` in JSON
+    pub fn push(&mut self, label: &str, key: &str, value: Metric<T>) {
+        self.rows.push(Row {
+            label: label.to_owned(),
+            key: key.to_owned(),
+            value,
+        })
+    }
+
+    /// Add a metric before all the others
+    pub fn prepend(&mut self, label: &str, key: &str, value: Metric<T>) {
+        self.rows.insert(
+            0,
+            Row {
+                label: label.to_owned(),
+                key: key.to_owned(),
+                value,
+            },
+        )
+    }
+
+    /// Metric of that JSON key
+    pub fn get(&self, key: &str) -> Option<&Metric<T>> {
+        self.rows
+            .iter()
+            .find(|row| row.key == key)
+            .map(|row| &row.value)
+    }
+
+    /// Keys of the metrics, in order
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.rows.iter().map(|row| row.key.as_str())
+    }
+
+    /// JSON Schema of the JSON output, with every metric required
+    pub fn json_schema(&self) -> Value {
+        let properties: Map<String, Value> = self
+            .rows
+            .iter()
+            .map(|row| (row.key.clone(), row.value.json_schema()))
+            .collect();
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": self.keys().collect::<Vec<_>>(),
+            "additionalProperties": false,
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        let map: Map<String, Value> = self
+            .rows
+            .iter()
+            .map(|row| (row.key.clone(), row.value.to_json()))
+            .collect();
+        Value::Object(map)
+    }
+}
+
+impl<T: Float + Display + LowerExp> Summary<T> {
+    /// Key and rendered value of every metric
+    pub fn values(&self, style: &Style) -> Vec<(&str, String)> {
+        self.rows
+            .iter()
+            .map(|row| (row.key.as_str(), row.value.render(style)))
+            .collect()
+    }
+
+    /// Text output, one 'label:<TAB>value' line per metric. With colors the
+    /// labels are bold, min and max are highlighted and non-zero counts of
+    /// dropped values are red
+    pub fn render(&self, style: &Style) -> String {
+        let fixed = |row: &Row<T>| match (&row.value, style.percentile_digits) {
+            (Metric::Float(v), Some(digits)) if is_percentile(&row.key) => {
+                Some(format!("{v:.digits$}"))
+            }
+            _ => None,
+        };
+        let width = self.rows.iter().filter_map(fixed).map(|v| v.len()).max();
+
+        let lines: Vec<String> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let value = match (fixed(row), width) {
+                    (Some(value), Some(width)) => format!("{value:>width$}"),
+                    _ => row.value.render(style),
+                };
+                let value = match style.width {
+                    // The value starts at the tab stop following the label and colon
+                    Some(width) => {
+                        let start = (row.label.chars().count() + 1) / 8 * 8 + 8;
+                        fit(value, &row.value, width.saturating_sub(start).max(1))
+                    }
+                    None => value,
+                };
+                if !style.color {
+                    return format!("{}:\t{value}", row.label);
+                }
+
+                let dropped = ["_dropped", "_skipped", "_clipped"]
+                    .iter()
+                    .any(|suffix| row.key.ends_with(suffix));
+                let value = match (&row.value, row.key.as_str()) {
+                    (Metric::Count(c), _) if dropped && *c > 0 => value.red().to_string(),
+                    (_, "min") => value.blue().to_string(),
+                    (_, "max") => value.magenta().to_string(),
+                    _ => value,
+                };
+                format!("{}:\t{value}", row.label.as_str().bold())
+            })
+            .collect();
+
+        format!("{}\n", lines.join("\n"))
+    }
+}
+
+/// Abbreviate `value` to at most `budget` characters, floats switching to
+/// scientific notation with fewer digits before anything is cut off
+fn fit<T: Float + LowerExp>(value: String, metric: &Metric<T>, budget: usize) -> String {
+    if value.chars().count() <= budget {
+        return value;
+    }
+    if let Metric::Float(v) = metric {
+        let shorter = (0..=6)
+            .rev()
+            .map(|digits| format!("{v:.digits$e}"))
+            .find(|s| s.len() <= budget);
+        if let Some(shorter) = shorter {
+            return shorter;
+        }
+    }
+    match budget {
+        0 => String::new(),
+        _ => value.chars().take(budget - 1).chain(['…']).collect(),
+    }
+}
+
+/// Whether `key` is that of a tail percentile, e.g. 'p99.9'
+fn is_percentile(key: &str) -> bool {
+    key.strip_prefix('p')
+        .is_some_and(|p| p.parse::<f64>().is_ok())
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// HTML table with one column per summary, metrics are matched across
+/// summaries by key and labels become column headers
+pub fn html_table<T: Float + Display + LowerExp>(
+    summaries: &[Summary<T>],
+    style: &Style,
+) -> String {
+    let find = |summary: &'_ Summary<T>, key: &str| -> Option<String> {
+        summary
+            .rows
+            .iter()
+            .find(|row| row.key == key)
+            .map(|row| row.value.render(style))
+    };
+
+    let mut keys: Vec<(&str, &str)> = vec![];
+    for row in summaries.iter().flat_map(|s| s.rows.iter()) {
+        if row.key != "label" && !keys.iter().any(|(key, _)| *key == row.key) {
+            keys.push((&row.key, &row.label));
+        }
+    }
+
+    let mut html = String::from("<table class=\"stats\">\n");
+    if summaries.iter().any(|s| find(s, "label").is_some()) {
+        html += "  <tr><th></th>";
+        for summary in summaries {
+            let label = find(summary, "label").unwrap_or_default();
+            html += &format!("<th>{}</th>", escape_html(&label));
+        }
+        html += "</tr>\n";
+    }
+    for (key, label) in keys {
+        html += &format!("  <tr><th>{}</th>", escape_html(label));
+        for summary in summaries {
+            let value = find(summary, key).unwrap_or_default();
+            html += &format!("<td>{}</td>", escape_html(&value));
+        }
+        html += "</tr>\n";
+    }
+    html += "</table>";
+
+    html
+}
+
+/// Wrap an HTML fragment in a minimal standalone document
+pub fn html_document(body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>stats</title>
+<style>
+table.stats {{ border-collapse: collapse; font-family: sans-serif; }}
+table.stats th, table.stats td {{ border: 1px solid #ccc; padding: 4px 8px; }}
+table.stats td {{ text-align: right; font-family: monospace; }}
+</style>
+</head>
+<body>
+{body}
+</body>
+</html>"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(notation: Notation) -> Style {
+        Style {
+            notation,
+            color: false,
+            percentile_digits: None,
+            width: None,
+            na: "NA".to_owned(),
+        }
+    }
+
+    #[test]
+    fn decimal_is_the_default_notation() {
+        let style = style(Notation::default());
+        assert_eq!(style.float(1e-9), "0.000000001");
+        assert_eq!(style.float(1e12), "1000000000000");
+        assert_eq!(style.float(0.00001), "0.00001");
+    }
+
+    #[test]
+    fn auto_notation_switches_to_scientific_for_extreme_magnitudes() {
+        let style = style(Notation::Auto);
+        assert_eq!(style.float(1e-9), "1e-9");
+        assert_eq!(style.float(1e12), "1000000000000");
+        assert_eq!(style.float(1e17), "1e17");
+    }
+
+    #[test]
+    fn scientific_notation_always_uses_an_exponent() {
+        let style = style(Notation::Scientific);
+        assert_eq!(style.float(1e-9), "1e-9");
+        assert_eq!(style.float(1e12), "1e12");
+    }
+}
@@ -0,0 +1,53 @@
+use num_traits::Float;
+
+use crate::sum::NeumaierSum;
+
+/// Error metrics of values seen as predictions of a constant target
+pub struct TargetError<T> {
+    target: T,
+    count: u64,
+    absolute: NeumaierSum,
+    squared: NeumaierSum,
+    bias: NeumaierSum,
+}
+
+impl<T: Float> TargetError<T> {
+    pub fn new(target: T) -> Self {
+        Self {
+            target,
+            count: 0,
+            absolute: NeumaierSum::default(),
+            squared: NeumaierSum::default(),
+            bias: NeumaierSum::default(),
+        }
+    }
+
+    /// Account for `val` seen `weight` times
+    pub fn update(&mut self, val: T, weight: u64) {
+        let error = (val - self.target).to_f64().unwrap();
+        let times = weight as f64;
+        self.absolute.update(error.abs() * times);
+        self.squared.update(error * error * times);
+        self.bias.update(error * times);
+        self.count += weight;
+    }
+
+    fn mean(&self, sum: &NeumaierSum) -> T {
+        T::from(sum.get() / self.count as f64).unwrap()
+    }
+
+    /// Mean absolute error
+    pub fn mae(&self) -> T {
+        self.mean(&self.absolute)
+    }
+
+    /// Root mean squared error
+    pub fn rmse(&self) -> T {
+        self.mean(&self.squared).sqrt()
+    }
+
+    /// Mean of `x - target`, positive when values overshoot the target
+    pub fn bias(&self) -> T {
+        self.mean(&self.bias)
+    }
+}
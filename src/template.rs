@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+#[derive(Clone)]
+enum Part {
+    Text(String),
+    Key(String),
+}
+
+/// Text with '{key}' placeholders replaced by the values of summary metrics,
+/// '{{' and '}}' being literal braces
+#[derive(Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut key = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => key.push(c),
+                            None => return Err(format!("Unclosed '{{{key}' in '{s}'")),
+                        }
+                    }
+                    if key.is_empty() || key.contains('{') {
+                        return Err(format!("Invalid placeholder '{{{key}}}' in '{s}'"));
+                    }
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                    parts.push(Part::Key(key));
+                }
+                '}' => return Err(format!("Unmatched '}}' in '{s}', use '}}}}' for a brace")),
+                c => text.push(c),
+            }
+        }
+        parts.push(Part::Text(text));
+
+        Ok(Self { parts })
+    }
+}
+
+impl Template {
+    /// Keys of the placeholders, in order
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Key(key) => Some(key.as_str()),
+            Part::Text(_) => None,
+        })
+    }
+
+    /// Fill in the placeholders from rendered metric values, missing ones are `na`
+    pub fn render(&self, values: &[(&str, String)], na: &str) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.as_str(),
+                Part::Key(key) => values
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map_or(na, |(_, value)| value.as_str()),
+            })
+            .collect()
+    }
+}
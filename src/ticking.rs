@@ -0,0 +1,38 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// Items of an iterator read on a background thread, `None` being yielded
+/// whenever no item came for `tick`, so that time based checks still run
+/// while the input stalls
+pub struct Ticking<I> {
+    items: Receiver<I>,
+    tick: Duration,
+}
+
+impl<I: Send + 'static> Ticking<I> {
+    pub fn spawn(iter: impl Iterator<Item = I> + Send + 'static, tick: Duration) -> Self {
+        // Bounded so that reading doesn't run far ahead of processing
+        let (sender, items) = mpsc::sync_channel(1024);
+        thread::spawn(move || {
+            for item in iter {
+                if sender.send(item).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { items, tick }
+    }
+}
+
+impl<I> Iterator for Ticking<I> {
+    type Item = Option<I>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.items.recv_timeout(self.tick) {
+            Ok(item) => Some(Some(item)),
+            Err(RecvTimeoutError::Timeout) => Some(None),
+            Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use num_traits::Float;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TransformError {
+    #[error("Unexpected character '{0}' in transform expression")]
+    UnexpectedChar(char),
+    #[error("Invalid number '{0}' in transform expression")]
+    InvalidNumber(String),
+    #[error("Unexpected end of transform expression")]
+    UnexpectedEnd,
+    #[error("Unexpected '{0}' in transform expression")]
+    UnexpectedToken(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Var,
+    Op(char),
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+/// Arithmetic expression over a single variable `x`, applied to each
+/// value before it is added to the statistics.
+///
+/// Supports `+ - * / ^`, unary minus and parentheses. All arithmetic is
+/// done in floating point, so `5/9` is not truncated.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Num(f64),
+    Var,
+    Neg(Box<Expr>),
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn eval<T: Float>(&self, x: T) -> T {
+        match self {
+            Expr::Num(n) => T::from(*n).unwrap(),
+            Expr::Var => x,
+            Expr::Neg(e) => -e.eval(x),
+            Expr::Bin(op, l, r) => {
+                let (l, r) = (l.eval(x), r.eval(x));
+                match op {
+                    BinOp::Add => l + r,
+                    BinOp::Sub => l - r,
+                    BinOp::Mul => l * r,
+                    BinOp::Div => l / r,
+                    BinOp::Pow => l.powf(r),
+                }
+            }
+        }
+    }
+}
+
+/// Fully parenthesized form, showing how the expression was parsed
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Num(n) => write!(f, "{n}"),
+            Expr::Var => write!(f, "x"),
+            Expr::Neg(e) => write!(f, "(-{e})"),
+            Expr::Bin(op, l, r) => {
+                let op = match op {
+                    BinOp::Add => '+',
+                    BinOp::Sub => '-',
+                    BinOp::Mul => '*',
+                    BinOp::Div => '/',
+                    BinOp::Pow => '^',
+                };
+                write!(f, "({l} {op} {r})")
+            }
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = TransformError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.expr()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(tok) => Err(TransformError::UnexpectedToken(describe(tok))),
+        }
+    }
+}
+
+fn describe(tok: &Token) -> String {
+    match tok {
+        Token::Num(n) => n.to_string(),
+        Token::Var => "x".to_owned(),
+        Token::Op(c) => c.to_string(),
+        Token::LParen => "(".to_owned(),
+        Token::RParen => ")".to_owned(),
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, TransformError> {
+    let mut tokens = vec![];
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            ' ' | '\t' => {}
+            'x' => tokens.push(Token::Var),
+            '+' | '-' | '*' | '/' | '^' => tokens.push(Token::Op(c)),
+            '(' => tokens.push(Token::LParen),
+            ')' => tokens.push(Token::RParen),
+            '0'..='9' | '.' => {
+                let mut end = start + c.len_utf8();
+                let mut prev = c;
+                while let Some(&(i, next)) = chars.peek() {
+                    let exponent_sign = (next == '-' || next == '+') && matches!(prev, 'e' | 'E');
+                    if next.is_ascii_digit() || matches!(next, '.' | 'e' | 'E') || exponent_sign {
+                        end = i + next.len_utf8();
+                        prev = next;
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let literal = &s[start..end];
+                let num = literal
+                    .parse::<f64>()
+                    .map_err(|_| TransformError::InvalidNumber(literal.to_owned()))?;
+                tokens.push(Token::Num(num));
+            }
+            _ => return Err(TransformError::UnexpectedChar(c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        tok
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, TransformError> {
+        let mut lhs = self.term()?;
+        while let Some(Token::Op(c @ ('+' | '-'))) = self.peek() {
+            let op = if *c == '+' { BinOp::Add } else { BinOp::Sub };
+            self.next();
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Expr, TransformError> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Op(c @ ('*' | '/'))) = self.peek() {
+            let op = if *c == '*' { BinOp::Mul } else { BinOp::Div };
+            self.next();
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    // unary := '-' unary | power
+    fn unary(&mut self) -> Result<Expr, TransformError> {
+        if let Some(Token::Op('-')) = self.peek() {
+            self.next();
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    // power := atom ('^' unary)?, right associative
+    fn power(&mut self) -> Result<Expr, TransformError> {
+        let base = self.atom()?;
+        if let Some(Token::Op('^')) = self.peek() {
+            self.next();
+            return Ok(Expr::Bin(
+                BinOp::Pow,
+                Box::new(base),
+                Box::new(self.unary()?),
+            ));
+        }
+        Ok(base)
+    }
+
+    // atom := number | 'x' | '(' expr ')'
+    fn atom(&mut self) -> Result<Expr, TransformError> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Expr::Num(n)),
+            Some(Token::Var) => Ok(Expr::Var),
+            Some(Token::LParen) => {
+                let inner = self.expr()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(inner),
+                    Some(tok) => Err(TransformError::UnexpectedToken(describe(&tok))),
+                    None => Err(TransformError::UnexpectedEnd),
+                }
+            }
+            Some(tok) => Err(TransformError::UnexpectedToken(describe(&tok))),
+            None => Err(TransformError::UnexpectedEnd),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use num_traits::Float;
+use thiserror::Error;
+
+use crate::parse;
+
+#[derive(Error, Debug)]
+pub enum UnitError {
+    #[error("Unknown unit '{0}'")]
+    Unknown(String),
+    #[error("Unit '{unit}' can't be converted to '{base}'")]
+    Mismatch { unit: String, base: String },
+    #[error("No number before the unit")]
+    NoNumber,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Dimension {
+    /// Bare multipliers such as 'k' or 'Mi'
+    Scalar,
+    Time,
+    Bytes,
+}
+
+// Symbol, dimension and factor to the base unit of the dimension
+const UNITS: &[(&str, Dimension, f64)] = &[
+    ("", Dimension::Scalar, 1.0),
+    ("k", Dimension::Scalar, 1e3),
+    ("K", Dimension::Scalar, 1e3),
+    ("M", Dimension::Scalar, 1e6),
+    ("G", Dimension::Scalar, 1e9),
+    ("T", Dimension::Scalar, 1e12),
+    ("Ki", Dimension::Scalar, 1024.0),
+    ("Mi", Dimension::Scalar, 1048576.0),
+    ("Gi", Dimension::Scalar, 1073741824.0),
+    ("Ti", Dimension::Scalar, 1099511627776.0),
+    ("ns", Dimension::Time, 1e-9),
+    ("us", Dimension::Time, 1e-6),
+    ("µs", Dimension::Time, 1e-6),
+    ("ms", Dimension::Time, 1e-3),
+    ("s", Dimension::Time, 1.0),
+    ("min", Dimension::Time, 60.0),
+    ("h", Dimension::Time, 3600.0),
+    ("B", Dimension::Bytes, 1.0),
+    ("kB", Dimension::Bytes, 1e3),
+    ("KB", Dimension::Bytes, 1e3),
+    ("MB", Dimension::Bytes, 1e6),
+    ("GB", Dimension::Bytes, 1e9),
+    ("TB", Dimension::Bytes, 1e12),
+    ("KiB", Dimension::Bytes, 1024.0),
+    ("MiB", Dimension::Bytes, 1048576.0),
+    ("GiB", Dimension::Bytes, 1073741824.0),
+    ("TiB", Dimension::Bytes, 1099511627776.0),
+];
+
+/// A unit values can be expressed in, e.g. 'ms' or 'GiB'
+#[derive(Clone, Debug)]
+pub struct Unit {
+    symbol: String,
+    dimension: Dimension,
+    factor: f64,
+}
+
+impl FromStr for Unit {
+    type Err = UnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        UNITS
+            .iter()
+            .find(|(symbol, _, _)| *symbol == s)
+            .map(|&(symbol, dimension, factor)| Unit {
+                symbol: symbol.to_owned(),
+                dimension,
+                factor,
+            })
+            .ok_or_else(|| UnitError::Unknown(s.to_owned()))
+    }
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.symbol)
+    }
+}
+
+/// Parse a number followed by an optional unit, e.g. '3.5ms' or '2 GiB', and
+/// express it in `base`. Without a base, values are expressed in the base unit
+/// of their dimension: seconds, bytes or no unit.
+pub fn parse_with_unit<T: Float>(s: &str, base: Option<&Unit>) -> Result<T, UnitError> {
+    let s = s.trim();
+    let (number, suffix) = s.split_at(parse::numeric_prefix_len(s));
+    let number: f64 = number.parse().map_err(|_| UnitError::NoNumber)?;
+    let unit: Unit = suffix.trim_start().parse()?;
+
+    let value = match base {
+        Some(base) if base.dimension != unit.dimension => {
+            return Err(UnitError::Mismatch {
+                unit: unit.symbol,
+                base: base.symbol.clone(),
+            })
+        }
+        Some(base) => number * unit.factor / base.factor,
+        None => number * unit.factor,
+    };
+    Ok(T::from(value).unwrap())
+}
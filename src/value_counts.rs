@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use num_traits::Float;
+
+use crate::distinct::value_key;
+
+/// Occurrences of each distinct value, for at most `cap` distinct values
+pub struct ValueCounts {
+    counts: HashMap<u64, usize>,
+    cap: usize,
+    total: usize,
+    /// Whether values were left uncounted because the map was full
+    pub overflowed: bool,
+}
+
+impl ValueCounts {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            counts: HashMap::new(),
+            cap,
+            total: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Count `n` occurrences of `val`
+    pub fn update<T: Float>(&mut self, val: T, n: usize) {
+        self.total += n;
+        let key = value_key(val);
+        if let Some(count) = self.counts.get_mut(&key) {
+            *count += n;
+        } else if self.counts.len() < self.cap {
+            self.counts.insert(key, n);
+        } else {
+            self.overflowed = true;
+        }
+    }
+
+    /// Number of distinct values counted
+    pub fn distinct(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// The `n` most frequent values with their count and their proportion of
+    /// all the values seen, by descending count then ascending value
+    pub fn top<T: Float>(&self, n: usize) -> Vec<(T, usize, f64)> {
+        let mut counts: Vec<(f64, usize)> = self
+            .counts
+            .iter()
+            .map(|(&bits, &count)| (f64::from_bits(bits), count))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.total_cmp(&b.0)));
+
+        counts
+            .into_iter()
+            .take(n)
+            .map(|(val, count)| {
+                let proportion = count as f64 / self.total as f64;
+                (T::from(val).unwrap(), count, proportion)
+            })
+            .collect()
+    }
+}
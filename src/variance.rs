@@ -0,0 +1,35 @@
+use num_traits::Float;
+
+/// Running sample variance with Welford's algorithm, in double precision.
+///
+/// Repeated values are added in one step as a chunk of identical values, by
+/// merging its count and mean (its own spread being zero) with the running ones.
+#[derive(Default)]
+pub struct WeightedVariance {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl WeightedVariance {
+    /// Account for `val` seen `weight` times
+    pub fn update<T: Float>(&mut self, val: T, weight: u64) {
+        if weight == 0 {
+            return;
+        }
+        let (val, weight_f) = (val.to_f64().unwrap(), weight as f64);
+        let count = self.count as f64 + weight_f;
+        let delta = val - self.mean;
+        self.mean += delta * weight_f / count;
+        self.m2 += delta * delta * self.count as f64 * weight_f / count;
+        self.count += weight;
+    }
+
+    /// Sample variance, 0 until two values are seen
+    pub fn get<T: Float>(&self) -> T {
+        if self.count < 2 {
+            return T::zero();
+        }
+        T::from(self.m2 / (self.count - 1) as f64).unwrap()
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::VecDeque;
+
+use num_traits::{Float, FromPrimitive};
+
+/// Ring buffer over the last `size` values, used to compute a trimmed
+/// moving average that is robust to spikes in live streams.
+pub struct TrimmedWindow<T> {
+    values: VecDeque<T>,
+    size: usize,
+    trim: f64,
+}
+
+impl<T> TrimmedWindow<T>
+where
+    T: Float + FromPrimitive,
+{
+    /// `trim` is the fraction of values dropped from each end of the window
+    pub fn new(size: usize, trim: f64) -> Self {
+        Self {
+            // Grown as values come, `size` may be far larger than the input
+            values: VecDeque::with_capacity(size.min(1024)),
+            size,
+            trim,
+        }
+    }
+
+    pub fn update(&mut self, val: T) {
+        if self.values.len() == self.size {
+            self.values.pop_front();
+        }
+        self.values.push_back(val);
+    }
+
+    /// Add `n` copies of a value, only the last ones that fit in the window matter
+    pub fn update_repeated(&mut self, val: T, n: u64) {
+        for _ in 0..n.min(self.size as u64) {
+            self.update(val);
+        }
+    }
+
+    /// Mean of the window once the extremes are dropped. The window is
+    /// small so it is sorted on demand rather than kept ordered.
+    pub fn trimmed_mean(&self) -> Option<T> {
+        if self.values.is_empty() {
+            return None;
+        }
+
+        let mut sorted: Vec<T> = self.values.iter().copied().collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let cut = (sorted.len() as f64 * self.trim).floor() as usize;
+        let kept = &sorted[cut..sorted.len() - cut];
+        if kept.is_empty() {
+            return None;
+        }
+
+        let sum = kept.iter().fold(T::zero(), |acc, &v| acc + v);
+        Some(sum / T::from_usize(kept.len()).unwrap())
+    }
+}